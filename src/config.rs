@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use crate::declarative::RuleSpec;
 use std::path::PathBuf;
 use anyhow::Result;
 use std::fs;
@@ -12,6 +13,28 @@ pub struct Config {
     pub no_colors: Option<bool>,
     pub priority: Option<std::collections::HashMap<String, usize>>,
     pub history_limit: Option<usize>,
+    /// Replace the call to the shell function with the correction in history.
+    pub alter_history: Option<bool>,
+    /// Milliseconds a rule may take before its corrections are dropped.
+    pub rule_timeout: Option<u64>,
+    /// Per-rule overrides of `rule_timeout`, by rule name.
+    pub rule_timeouts: Option<std::collections::HashMap<String, u64>>,
+    /// Rank corrections the user picked before higher.
+    pub learn: Option<bool>,
+    /// Re-run commands in `rerun_locale` so their messages are in English.
+    pub force_locale: Option<bool>,
+    /// Locale for `force_locale`; by default `C.UTF-8` where available, which
+    /// keeps non-ASCII output intact, otherwise `C`.
+    pub rerun_locale: Option<String>,
+    /// Largest typo distance for `unknown_command` to suggest a command, where
+    /// swapped letters and neighbouring keys count as half an edit.
+    pub typo_max_distance: Option<f64>,
+    /// Smallest similarity, from 0 to 1, for `unknown_command` to suggest a command.
+    pub typo_min_similarity: Option<f64>,
+    /// Print rule timings and timeouts to stderr.
+    pub debug: Option<bool>,
+    /// Declarative `[[rule]]` entries.
+    pub rule: Option<Vec<RuleSpec>>,
 }
 
 impl Default for Config {
@@ -24,6 +47,16 @@ impl Default for Config {
             no_colors: Some(false),
             priority: None,
             history_limit: Some(100),
+            alter_history: Some(true),
+            rule_timeout: Some(1000),
+            rule_timeouts: None,
+            learn: Some(true),
+            force_locale: Some(true),
+            rerun_locale: None,
+            typo_max_distance: Some(crate::typo::DEFAULT_MAX_DISTANCE),
+            typo_min_similarity: Some(crate::typo::DEFAULT_MIN_SIMILARITY),
+            debug: Some(false),
+            rule: None,
         }
     }
 }
@@ -55,6 +88,7 @@ mod tests {
             require_confirmation = false
             no_colors = true
             history_limit = 2000
+            alter_history = false
 
             [priority]
             git = 100
@@ -69,17 +103,35 @@ mod tests {
         assert_eq!(config.require_confirmation, Some(false));
         assert_eq!(config.no_colors, Some(true));
         assert_eq!(config.history_limit, Some(2000));
+        assert_eq!(config.alter_history, Some(false));
 
         let priority = config.priority.unwrap();
         assert_eq!(priority.get("git"), Some(&100));
         assert_eq!(priority.get("ls"), Some(&50));
     }
 
+    #[test]
+    fn test_config_declarative_rules() {
+        let toml_str = r#"
+            [[rule]]
+            name = "gti"
+            match_script = "^gti "
+            replace = "git"
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let rules = config.rule.unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].name, "gti");
+        assert_eq!(rules[0].match_stderr, None);
+    }
+
     #[test]
     fn test_default_config() {
         let config = Config::default();
         assert_eq!(config.wait_command, Some(3));
         assert_eq!(config.require_confirmation, Some(true));
         assert_eq!(config.no_colors, Some(false));
+        assert_eq!(config.alter_history, Some(true));
     }
 }
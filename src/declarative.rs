@@ -0,0 +1,280 @@
+use crate::types::{Command, Correction};
+use crate::rules::Rule;
+use crate::paths::rule_files;
+use crate::testing::is_test_file;
+use anyhow::{Result, anyhow};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// A `[[rule]]` entry, either in `config.toml` or a `*.toml` file in a rules
+/// directory.
+///
+/// `replace` is a template: `$1`/`${1}` refer to groups of `match_stderr` (or of
+/// `match_script` when no stderr pattern is given), `${name}` to a named group
+/// of either pattern, `$$` is a literal `$`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleSpec {
+    pub name: String,
+    pub match_script: Option<String>,
+    pub match_stderr: Option<String>,
+    pub match_exit_code: Option<i32>,
+    pub replace: String,
+    pub priority: Option<usize>,
+    pub side_effect: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RuleFile {
+    #[serde(default)]
+    rule: Vec<RuleSpec>,
+}
+
+#[derive(Debug)]
+pub struct DeclarativeRule {
+    name: String,
+    match_script: Option<Regex>,
+    match_stderr: Option<Regex>,
+    match_exit_code: Option<i32>,
+    replace: String,
+    priority: usize,
+    side_effect: bool,
+}
+
+impl DeclarativeRule {
+    pub fn new(spec: &RuleSpec) -> Result<Self> {
+        // Without a pattern the rule would match every command
+        if spec.match_script.is_none() && spec.match_stderr.is_none() {
+            return Err(anyhow!("rule {}: needs match_script or match_stderr", spec.name));
+        }
+
+        let compile = |pattern: &Option<String>| -> Result<Option<Regex>> {
+            pattern.as_deref()
+                .map(|p| Regex::new(p).map_err(|e| anyhow!("rule {}: {}", spec.name, e)))
+                .transpose()
+        };
+
+        Ok(Self {
+            name: spec.name.clone(),
+            match_script: compile(&spec.match_script)?,
+            match_stderr: compile(&spec.match_stderr)?,
+            match_exit_code: spec.match_exit_code,
+            replace: spec.replace.clone(),
+            priority: spec.priority.unwrap_or(100),
+            side_effect: spec.side_effect.unwrap_or(false),
+        })
+    }
+
+    fn captures<'a>(&self, command: &'a Command) -> Option<(Option<Captures<'a>>, Option<Captures<'a>>)> {
+        if let Some(code) = self.match_exit_code {
+            if command.exit_code != Some(code) {
+                return None;
+            }
+        }
+
+        let script = match &self.match_script {
+            Some(re) => Some(re.captures(&command.script)?),
+            None => None,
+        };
+        let stderr = match &self.match_stderr {
+            Some(re) => Some(re.captures(&command.stderr)?),
+            None => None,
+        };
+        Some((script, stderr))
+    }
+}
+
+impl Rule for DeclarativeRule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn matches(&self, command: &Command) -> bool {
+        self.captures(command).is_some()
+    }
+
+    fn generate_corrections(&self, command: &Command) -> Vec<Correction> {
+        let (script, stderr) = match self.captures(command) {
+            Some(c) => c,
+            None => return vec![],
+        };
+
+        let mut named = HashMap::new();
+        for (re, caps) in [(&self.match_script, &script), (&self.match_stderr, &stderr)] {
+            if let (Some(re), Some(caps)) = (re, caps) {
+                for name in re.capture_names().flatten() {
+                    if let Some(m) = caps.name(name) {
+                        named.insert(name.to_string(), m.as_str().to_string());
+                    }
+                }
+            }
+        }
+
+        let numbered = stderr.as_ref().or(script.as_ref());
+        let new_cmd = expand(&self.replace, numbered, &named);
+        vec![Correction::new(new_cmd, self.side_effect, self.priority)]
+    }
+}
+
+fn expand(template: &str, numbered: Option<&Captures>, named: &HashMap<String, String>) -> String {
+    let lookup = |key: &str| -> String {
+        match key.parse::<usize>() {
+            Ok(i) => numbered.and_then(|c| c.get(i)).map_or("", |m| m.as_str()).to_string(),
+            Err(_) => named.get(key).cloned().unwrap_or_default(),
+        }
+    };
+
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(pos) = rest.find('$') {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos + 1..];
+
+        if let Some(tail) = rest.strip_prefix('$') {
+            out.push('$');
+            rest = tail;
+        } else if let Some(end) = rest.strip_prefix('{').and_then(|r| r.find('}')) {
+            out.push_str(&lookup(&rest[1..end + 1]));
+            rest = &rest[end + 2..];
+        } else {
+            let len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            if len == 0 {
+                out.push('$');
+            } else {
+                out.push_str(&lookup(&rest[..len]));
+                rest = &rest[len..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Builds rules from specs, reporting and skipping ones with invalid or
+/// missing patterns.
+pub fn build_declarative_rules(specs: &[RuleSpec]) -> Vec<DeclarativeRule> {
+    specs.iter()
+        .filter_map(|spec| match DeclarativeRule::new(spec) {
+            Ok(rule) => Some(rule),
+            Err(e) => {
+                eprintln!("Invalid declarative rule: {}", e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Loads `[[rule]]` entries from the `*.toml` files in `dirs`, later
+/// directories shadowing earlier ones by file name.
+pub fn load_declarative_rules(dirs: &[PathBuf]) -> Vec<DeclarativeRule> {
+    let mut rules = Vec::new();
+
+    for path in rule_files(dirs, "toml").into_iter().filter(|p| !is_test_file(p)) {
+        let parsed = fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|contents| toml::from_str::<RuleFile>(&contents).map_err(anyhow::Error::from));
+        match parsed {
+            Ok(file) => rules.extend(build_declarative_rules(&file.rule)),
+            Err(e) => eprintln!("Failed to load rules from {}: {}", path.display(), e),
+        }
+    }
+
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn spec(toml_str: &str) -> RuleSpec {
+        let file: RuleFile = toml::from_str(toml_str).unwrap();
+        file.rule[0].clone()
+    }
+
+    #[test]
+    fn test_declarative_rule_stderr_capture() {
+        let rule = DeclarativeRule::new(&spec(r#"
+            [[rule]]
+            name = "kubectl_typo"
+            match_script = '^kubectl \S+(?P<args>.*)'
+            match_stderr = 'unknown command "(\S+)".*\n.*Did you mean (?P<fix>\w+)'
+            replace = "kubectl ${fix}${args}"
+        "#)).unwrap();
+
+        let command = Command::new(
+            "kubectl gt pods".to_string(),
+            "".to_string(),
+            "Error: unknown command \"gt\" for \"kubectl\"\nDid you mean get?".to_string(),
+        );
+
+        assert!(rule.matches(&command));
+        let corrections = rule.generate_corrections(&command);
+        assert_eq!(corrections.len(), 1);
+        assert_eq!(corrections[0].command, "kubectl get pods");
+    }
+
+    #[test]
+    fn test_declarative_rule_script_capture_and_exit_code() {
+        let rule = DeclarativeRule::new(&spec(r#"
+            [[rule]]
+            name = "npm_run"
+            match_script = '^npm (\w+)$'
+            match_exit_code = 1
+            replace = "npm run $1"
+            priority = 80
+        "#)).unwrap();
+
+        let command = Command::new("npm build".to_string(), "".to_string(), "".to_string());
+        assert!(!rule.matches(&command));
+
+        let command = command.with_exit_code(Some(1));
+        assert!(rule.matches(&command));
+        let corrections = rule.generate_corrections(&command);
+        assert_eq!(corrections[0].command, "npm run build");
+        assert_eq!(corrections[0].priority, 80);
+    }
+
+    #[test]
+    fn test_declarative_rule_needs_a_pattern() {
+        let err = DeclarativeRule::new(&spec(r#"
+            [[rule]]
+            name = "everything"
+            replace = "ls"
+        "#)).unwrap_err();
+        assert!(err.to_string().contains("needs match_script or match_stderr"));
+    }
+
+    #[test]
+    fn test_expand_escapes() {
+        let named = HashMap::new();
+        assert_eq!(expand("echo $$HOME $ $9", None, &named), "echo $HOME $ ");
+    }
+
+    #[test]
+    fn test_load_declarative_rules() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("team.toml"), r#"
+            [[rule]]
+            name = "one"
+            match_script = "^one"
+            replace = "two"
+
+            [[rule]]
+            name = "bad"
+            match_script = "("
+            replace = "never"
+
+            [[rule]]
+            name = "everything"
+            match_exit_code = 1
+            replace = "never"
+        "#).unwrap();
+
+        let rules = load_declarative_rules(&[dir.path().to_path_buf()]);
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].name(), "one");
+    }
+}
@@ -1,11 +1,32 @@
 use crate::rules::Rule;
 use crate::types::{Command, Correction};
 use crate::config::Config;
-use std::sync::Arc;
+use crate::learning::Preferences;
+use std::collections::{BTreeSet, HashSet};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_RULE_TIMEOUT_MS: u64 = 1000;
+
+/// What came of running every rule against a command.
+#[derive(Debug, Default)]
+pub struct Evaluation {
+    /// Corrections from the rules that finished in time, best first.
+    pub corrections: Vec<Correction>,
+    /// Time taken by each rule that finished, in registration order.
+    pub timings: Vec<(String, Duration)>,
+    /// Rules that missed their deadline; their corrections are dropped.
+    pub timed_out: Vec<String>,
+    /// Rules that panicked.
+    pub panicked: Vec<String>,
+}
 
 pub struct Engine {
     rules: Vec<Arc<dyn Rule>>,
     config: Config,
+    preferences: Option<Arc<Preferences>>,
 }
 
 impl Engine {
@@ -13,6 +34,14 @@ impl Engine {
         Self {
             rules: Vec::new(),
             config,
+            preferences: None,
+        }
+    }
+
+    /// Boosts corrections the user picked before, unless `learn` is off.
+    pub fn set_preferences(&mut self, preferences: Preferences) {
+        if self.config.learn.unwrap_or(true) {
+            self.preferences = Some(Arc::new(preferences));
         }
     }
 
@@ -33,19 +62,141 @@ impl Engine {
     }
 
     pub fn get_corrections(&self, command: &Command) -> Vec<Correction> {
-        let mut corrections = Vec::new();
+        self.evaluate(command).corrections
+    }
+
+    /// How long `rule` may run, from `rule_timeouts` or `rule_timeout`.
+    fn timeout(&self, rule: &str) -> Duration {
+        let ms = self.config.rule_timeouts.as_ref()
+            .and_then(|timeouts| timeouts.get(rule).copied())
+            .or(self.config.rule_timeout)
+            .unwrap_or(DEFAULT_RULE_TIMEOUT_MS);
+        Duration::from_millis(ms)
+    }
 
-        for rule in &self.rules {
-            if rule.matches(command) {
-                let mut new_corrections = rule.generate_corrections(command);
-                corrections.append(&mut new_corrections);
+    /// Starts every rule on its own thread and returns their results as they
+    /// finish. Rules that miss their deadline are left to finish in the
+    /// background and ignored.
+    pub fn stream(&self, command: &Command) -> CorrectionStream {
+        let start = Instant::now();
+        let command = Arc::new(command.clone());
+        let (tx, rx) = mpsc::channel();
+
+        for (index, rule) in self.rules.iter().enumerate() {
+            let rule = Arc::clone(rule);
+            let command = Arc::clone(&command);
+            let tx = tx.clone();
+            thread::spawn(move || {
+                let result = catch_unwind(AssertUnwindSafe(|| {
+                    if rule.matches(&command) { rule.generate_corrections(&command) } else { Vec::new() }
+                }));
+                let _ = tx.send((index, result.ok(), start.elapsed()));
+            });
+        }
+
+        CorrectionStream {
+            rx,
+            script: command.script.clone(),
+            preferences: self.preferences.clone(),
+            start,
+            names: self.rules.iter().map(|r| r.name().to_string()).collect(),
+            deadlines: self.rules.iter().map(|r| start + self.timeout(r.name())).collect(),
+            pending: (0..self.rules.len()).collect(),
+        }
+    }
+
+    /// Runs every rule and collects the corrections of those that finish
+    /// before their deadline. Corrections are ordered by priority, then by rule
+    /// registration order, whatever order the rules finish in.
+    pub fn evaluate(&self, command: &Command) -> Evaluation {
+        self.stream(command).evaluate()
+    }
+}
+
+/// One rule that finished in time.
+#[derive(Debug)]
+pub struct RuleResult {
+    /// Registration order of the rule.
+    pub index: usize,
+    pub rule: String,
+    /// `None` if the rule panicked.
+    pub corrections: Option<Vec<Correction>>,
+    pub elapsed: Duration,
+}
+
+/// Rule results in the order the rules finish, from [`Engine::stream`]. Ends
+/// once every rule has finished or missed its deadline.
+pub struct CorrectionStream {
+    rx: mpsc::Receiver<(usize, Option<Vec<Correction>>, Duration)>,
+    script: String,
+    preferences: Option<Arc<Preferences>>,
+    start: Instant,
+    names: Vec<String>,
+    deadlines: Vec<Instant>,
+    pending: BTreeSet<usize>,
+}
+
+impl CorrectionStream {
+    /// Rules that haven't finished and are past their deadline, in
+    /// registration order.
+    pub fn timed_out(&self) -> Vec<String> {
+        let now = Instant::now();
+        self.pending.iter()
+            .filter(|&&i| self.deadlines[i] <= now)
+            .map(|&i| self.names[i].clone())
+            .collect()
+    }
+
+    /// Waits for the remaining rules, as [`Engine::evaluate`] does.
+    pub fn evaluate(mut self) -> Evaluation {
+        let mut results: Vec<RuleResult> = self.by_ref().collect();
+        results.sort_by_key(|r| r.index);
+
+        let mut evaluation = Evaluation { timed_out: self.timed_out(), ..Evaluation::default() };
+        for result in results {
+            match result.corrections {
+                Some(mut corrections) => {
+                    evaluation.corrections.append(&mut corrections);
+                    evaluation.timings.push((result.rule, result.elapsed));
+                }
+                None => evaluation.panicked.push(result.rule),
             }
         }
 
-        // Sort by priority
-        corrections.sort_by(|a, b| b.priority.cmp(&a.priority));
+        // Stable, so equal priorities keep registration order
+        evaluation.corrections.sort_by_key(|c| std::cmp::Reverse(c.priority));
+        // Several rules can suggest the same fix; keep the best ranked
+        let mut seen = HashSet::new();
+        evaluation.corrections.retain(|c| seen.insert(c.command.clone()));
+        evaluation
+    }
+}
+
+impl Iterator for CorrectionStream {
+    type Item = RuleResult;
+
+    fn next(&mut self) -> Option<RuleResult> {
+        loop {
+            let now = Instant::now();
+            let next_deadline = self.pending.iter()
+                .map(|&i| self.deadlines[i])
+                .filter(|&deadline| deadline > now)
+                .min()?;
 
-        corrections
+            match self.rx.recv_timeout(next_deadline - now) {
+                Ok((index, mut corrections, elapsed)) if self.start + elapsed <= self.deadlines[index] => {
+                    self.pending.remove(&index);
+                    if let (Some(preferences), Some(corrections)) = (&self.preferences, &mut corrections) {
+                        for correction in corrections {
+                            correction.priority += preferences.boost(&self.script, &correction.command);
+                        }
+                    }
+                    return Some(RuleResult { index, rule: self.names[index].clone(), corrections, elapsed });
+                }
+                Ok(_) | Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => return None,
+            }
+        }
     }
 }
 
@@ -53,11 +204,19 @@ impl Engine {
 mod tests {
     use super::*;
     use crate::types::{Command, Correction};
+    use std::sync::Mutex;
+
+    /// How long a gated rule waits to be released; far beyond any deadline
+    /// in these tests, so a rule that is waited for shows up as a failure
+    /// rather than a hang.
+    const GATE_TIMEOUT: Duration = Duration::from_secs(10);
 
     #[derive(Debug)]
     struct MockRule {
         name: String,
         priority: usize,
+        delay: Duration,
+        gate: Option<Mutex<mpsc::Receiver<()>>>,
     }
 
     impl MockRule {
@@ -65,8 +224,21 @@ mod tests {
             Self {
                 name: name.to_string(),
                 priority,
+                delay: Duration::ZERO,
+                gate: None,
             }
         }
+
+        fn slow(name: &str, priority: usize, delay_ms: u64) -> Self {
+            Self { delay: Duration::from_millis(delay_ms), ..Self::new(name, priority) }
+        }
+
+        /// A rule that doesn't finish until the returned sender is used or
+        /// dropped, or [`GATE_TIMEOUT`] passes.
+        fn gated(name: &str, priority: usize) -> (Self, mpsc::Sender<()>) {
+            let (tx, rx) = mpsc::channel();
+            (Self { gate: Some(Mutex::new(rx)), ..Self::new(name, priority) }, tx)
+        }
     }
 
     impl Rule for MockRule {
@@ -74,6 +246,10 @@ mod tests {
             &self.name
         }
         fn matches(&self, _command: &Command) -> bool {
+            thread::sleep(self.delay);
+            if let Some(gate) = &self.gate {
+                let _ = gate.lock().unwrap().recv_timeout(GATE_TIMEOUT);
+            }
             true
         }
         fn generate_corrections(&self, _command: &Command) -> Vec<Correction> {
@@ -83,8 +259,10 @@ mod tests {
 
     #[test]
     fn test_register_rule_whitelist() {
-        let mut config = Config::default();
-        config.rules = Some(vec!["rule1".to_string()]);
+        let config = Config {
+            rules: Some(vec!["rule1".to_string()]),
+            ..Config::default()
+        };
 
         let mut engine = Engine::new(config);
         engine.register_rule(Arc::new(MockRule::new("rule1", 100)));
@@ -96,8 +274,10 @@ mod tests {
 
     #[test]
     fn test_register_rule_exclude() {
-        let mut config = Config::default();
-        config.exclude_rules = Some(vec!["rule2".to_string()]);
+        let config = Config {
+            exclude_rules: Some(vec!["rule2".to_string()]),
+            ..Config::default()
+        };
 
         let mut engine = Engine::new(config);
         engine.register_rule(Arc::new(MockRule::new("rule1", 100)));
@@ -124,4 +304,118 @@ mod tests {
         assert_eq!(corrections[1].command, "fixed_medium");
         assert_eq!(corrections[2].command, "fixed_low");
     }
+
+    #[test]
+    fn test_get_corrections_dedups() {
+        let mut engine = Engine::new(Config::default());
+        engine.register_rule(Arc::new(MockRule::new("same", 10)));
+        engine.register_rule(Arc::new(MockRule::new("same", 100)));
+
+        let command = Command::new("foo".to_string(), "".to_string(), "".to_string());
+        let corrections = engine.get_corrections(&command);
+        assert_eq!(corrections.len(), 1);
+        assert_eq!(corrections[0].priority, 100);
+    }
+
+    #[derive(Debug)]
+    struct PanickingRule;
+
+    impl Rule for PanickingRule {
+        fn name(&self) -> &str {
+            "panicking"
+        }
+        fn matches(&self, _command: &Command) -> bool {
+            panic!("broken rule")
+        }
+        fn generate_corrections(&self, _command: &Command) -> Vec<Correction> {
+            vec![]
+        }
+    }
+
+    #[test]
+    fn test_evaluate_drops_late_rules() {
+        // `slow` never finishes in time; `patient` takes longer than the
+        // default timeout but well within its own
+        let config = Config {
+            rule_timeout: Some(200),
+            rule_timeouts: Some(std::collections::HashMap::from([("patient".to_string(), 5000)])),
+            ..Config::default()
+        };
+        let mut engine = Engine::new(config);
+        let (slow, release) = MockRule::gated("slow", 100);
+        engine.register_rule(Arc::new(slow));
+        engine.register_rule(Arc::new(MockRule::new("fast", 50)));
+        engine.register_rule(Arc::new(MockRule::slow("patient", 10, 400)));
+        engine.register_rule(Arc::new(PanickingRule));
+
+        let command = Command::new("foo".to_string(), "".to_string(), "".to_string());
+        let started = Instant::now();
+        let evaluation = engine.evaluate(&command);
+        drop(release);
+
+        assert!(started.elapsed() < GATE_TIMEOUT / 2);
+        let commands: Vec<_> = evaluation.corrections.iter().map(|c| c.command.as_str()).collect();
+        assert_eq!(commands, vec!["fixed_fast", "fixed_patient"]);
+        assert_eq!(evaluation.timed_out, vec!["slow"]);
+        assert_eq!(evaluation.panicked, vec!["panicking"]);
+        let timed: Vec<_> = evaluation.timings.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(timed, vec!["fast", "patient"]);
+    }
+
+    #[test]
+    fn test_stream_yields_in_completion_order() {
+        let config = Config { rule_timeout: Some(GATE_TIMEOUT.as_millis() as u64), ..Config::default() };
+        let mut engine = Engine::new(config);
+        let (slow, release) = MockRule::gated("slow", 100);
+        engine.register_rule(Arc::new(slow));
+        engine.register_rule(Arc::new(MockRule::new("fast", 10)));
+
+        let command = Command::new("foo".to_string(), "".to_string(), "".to_string());
+        let mut stream = engine.stream(&command);
+        // `slow` can't finish before it's released
+        let first = stream.next().unwrap();
+        assert_eq!(first.rule, "fast");
+        assert_eq!(first.index, 1);
+        release.send(()).unwrap();
+        assert_eq!(stream.next().unwrap().rule, "slow");
+        assert!(stream.next().is_none());
+        assert!(stream.timed_out().is_empty());
+    }
+
+    #[test]
+    fn test_evaluate_order_is_deterministic() {
+        let mut engine = Engine::new(Config::default());
+        // Later rules finish first, but equal priorities keep registration order
+        engine.register_rule(Arc::new(MockRule::slow("first", 100, 60)));
+        engine.register_rule(Arc::new(MockRule::slow("second", 100, 30)));
+        engine.register_rule(Arc::new(MockRule::new("third", 100)));
+
+        let command = Command::new("foo".to_string(), "".to_string(), "".to_string());
+        let commands: Vec<_> = engine.get_corrections(&command).into_iter().map(|c| c.command).collect();
+        assert_eq!(commands, vec!["fixed_first", "fixed_second", "fixed_third"]);
+    }
+
+    #[test]
+    fn test_preferences_boost_picked_corrections() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("choices.toml");
+        let mut preferences = Preferences::load(&path);
+        preferences.record("foo", "fixed_low").unwrap();
+
+        let mut engine = Engine::new(Config::default());
+        engine.register_rule(Arc::new(MockRule::new("high", 100)));
+        engine.register_rule(Arc::new(MockRule::new("low", 50)));
+        engine.set_preferences(Preferences::load(&path));
+
+        let command = Command::new("foo".to_string(), "".to_string(), "".to_string());
+        let corrections = engine.get_corrections(&command);
+        assert_eq!(corrections[0].command, "fixed_low");
+
+        let config = Config { learn: Some(false), ..Config::default() };
+        let mut engine = Engine::new(config);
+        engine.register_rule(Arc::new(MockRule::new("high", 100)));
+        engine.register_rule(Arc::new(MockRule::new("low", 50)));
+        engine.set_preferences(Preferences::load(&path));
+        assert_eq!(engine.get_corrections(&command)[0].command, "fixed_high");
+    }
 }
@@ -0,0 +1,335 @@
+//! Index of the executables on `$PATH`, shared by every rule that needs to
+//! know whether a command exists or what it might have been meant to be.
+//!
+//! Listing `/usr/bin` and checking permissions on every file is slow, more so
+//! on network mounts, so the listing of each directory is cached on disk along
+//! with the directory's mtime and only re-read when that changes. Fuzzy lookups
+//! go through a BK-tree built from the cached names, by Damerau-Levenshtein
+//! distance so a swap of two letters counts as one edit.
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::UNIX_EPOCH;
+use strsim::damerau_levenshtein;
+
+static SHARED: Lazy<ExecutableIndex> = Lazy::new(|| {
+    let path = ExecutableIndex::default_path();
+    let mut index = ExecutableIndex::load(&path);
+    let path_var = env::var_os("PATH").unwrap_or_default();
+    index.refresh(&env::split_paths(&path_var).collect::<Vec<_>>());
+    let _ = index.save(&path);
+    index
+});
+
+/// The index of the current `$PATH`, loaded and refreshed on first use.
+pub fn shared() -> &'static ExecutableIndex {
+    &SHARED
+}
+
+/// Loads and refreshes the shared index on another thread. Join it before
+/// rules start, so listing a cold or slow `$PATH` doesn't count against the
+/// deadline of the rule that happens to ask first.
+pub fn warm_up() -> thread::JoinHandle<()> {
+    thread::spawn(|| {
+        shared();
+    })
+}
+
+/// Whether `name` is an executable on `$PATH`, or a path to one.
+pub fn command_exists(name: &str) -> bool {
+    if name.contains(std::path::MAIN_SEPARATOR) || name.contains('/') {
+        return is_executable(Path::new(name));
+    }
+    shared().contains(name)
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ExecutableIndex {
+    #[serde(default)]
+    dirs: Vec<IndexedDir>,
+    #[serde(skip)]
+    names: HashSet<String>,
+    #[serde(skip)]
+    tree: BkTree,
+    #[serde(skip)]
+    dirty: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct IndexedDir {
+    path: String,
+    /// Nanoseconds since the epoch.
+    mtime: u64,
+    names: Vec<String>,
+}
+
+impl ExecutableIndex {
+    pub fn default_path() -> PathBuf {
+        let cache_dir = dirs::cache_dir().unwrap_or_else(|| PathBuf::from("."));
+        cache_dir.join("ffs").join("executables.toml")
+    }
+
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Renamed into place so an interrupted save can't leave a truncated cache
+        let tmp = path.with_extension("toml.tmp");
+        fs::write(&tmp, toml::to_string(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Makes the index cover exactly `path_dirs`, re-listing the directories
+    /// that are new or whose mtime changed. Adding or removing a file updates
+    /// the mtime; making an existing file executable does not.
+    pub fn refresh(&mut self, path_dirs: &[PathBuf]) {
+        let mut dirs = Vec::new();
+        for dir in path_dirs {
+            let key = dir.to_string_lossy().to_string();
+            if dirs.iter().any(|d: &IndexedDir| d.path == key) {
+                continue;
+            }
+            let Some(mtime) = mtime(dir) else {
+                self.dirty |= self.dirs.iter().any(|d| d.path == key);
+                continue;
+            };
+
+            match self.dirs.iter().find(|d| d.path == key && d.mtime == mtime) {
+                Some(cached) => dirs.push(cached.clone()),
+                None => {
+                    dirs.push(IndexedDir { path: key, mtime, names: list_executables(dir) });
+                    self.dirty = true;
+                }
+            }
+        }
+        self.dirty |= dirs.len() != self.dirs.len();
+        self.dirs = dirs;
+
+        self.names = self.dirs.iter().flat_map(|d| d.names.iter().cloned()).collect();
+        self.tree = BkTree::default();
+        for name in &self.names {
+            self.tree.insert(name);
+        }
+    }
+
+    /// An index of just `names`, without touching the filesystem.
+    #[cfg(test)]
+    pub(crate) fn from_names<'a>(names: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut index = Self::default();
+        for name in names {
+            index.names.insert(name.to_string());
+            index.tree.insert(name);
+        }
+        index
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.contains(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(String::as_str)
+    }
+
+    /// Executables within `max_distance` edits of `target`, closest first.
+    pub fn find_similar(&self, target: &str, max_distance: usize) -> Vec<(String, usize)> {
+        let mut found = self.tree.find(target, max_distance);
+        found.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+        found
+    }
+}
+
+pub(crate) fn mtime(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64))
+}
+
+fn list_executables(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .into_iter()
+        .flat_map(|entries| entries.flatten())
+        .filter(|entry| is_executable(&entry.path()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    names.sort();
+    names
+}
+
+pub fn is_executable(path: &Path) -> bool {
+    // In Unix, check execute permission. In Windows, check extension.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Ok(metadata) = path.metadata() {
+            return metadata.is_file() && metadata.permissions().mode() & 0o111 != 0;
+        }
+    }
+    #[cfg(windows)]
+    {
+        if let Some(ext) = path.extension() {
+             let ext_str = ext.to_string_lossy().to_lowercase();
+             return ext_str == "exe" || ext_str == "cmd" || ext_str == "bat";
+        }
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    {
+        // Fallback for other OS or if we can't determine
+        if let Ok(metadata) = path.metadata() {
+            // Rough check: is it a file?
+             return metadata.is_file();
+        }
+    }
+
+    false
+}
+
+/// Words arranged by edit distance, so a lookup only compares against the
+/// few whose distance to each visited node is in range.
+#[derive(Debug, Default)]
+struct BkTree {
+    nodes: Vec<BkNode>,
+}
+
+#[derive(Debug)]
+struct BkNode {
+    word: String,
+    /// (distance to this node's word, index of the child)
+    children: Vec<(usize, usize)>,
+}
+
+impl BkTree {
+    fn insert(&mut self, word: &str) {
+        if self.nodes.is_empty() {
+            self.nodes.push(BkNode { word: word.to_string(), children: Vec::new() });
+            return;
+        }
+
+        let mut current = 0;
+        loop {
+            let distance = damerau_levenshtein(word, &self.nodes[current].word);
+            if distance == 0 {
+                return;
+            }
+            match self.nodes[current].children.iter().find(|(d, _)| *d == distance) {
+                Some(&(_, child)) => current = child,
+                None => {
+                    let child = self.nodes.len();
+                    self.nodes.push(BkNode { word: word.to_string(), children: Vec::new() });
+                    self.nodes[current].children.push((distance, child));
+                    return;
+                }
+            }
+        }
+    }
+
+    fn find(&self, target: &str, max_distance: usize) -> Vec<(String, usize)> {
+        let mut found = Vec::new();
+        let mut stack = if self.nodes.is_empty() { vec![] } else { vec![0] };
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            let distance = damerau_levenshtein(target, &node.word);
+            if distance <= max_distance {
+                found.push((node.word.clone(), distance));
+            }
+            // Triangle inequality: only children at distance ± max can match
+            stack.extend(node.children.iter()
+                .filter(|(d, _)| d.abs_diff(distance) <= max_distance)
+                .map(|(_, child)| *child));
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use tempfile::tempdir;
+
+    fn create_executable(dir: &Path, name: &str) {
+        let file = File::create(dir.join(name)).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = file.metadata().unwrap().permissions();
+            perms.set_mode(0o755);
+            file.set_permissions(perms).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_bk_tree_matches_linear_scan() {
+        let words = ["git", "gist", "grep", "egrep", "cargo", "cat", "cut", "chmod", "chown", "ls", "lsof", "make"];
+        let mut tree = BkTree::default();
+        for word in words {
+            tree.insert(word);
+        }
+
+        for target in ["gti", "crago", "chmd", "mkae", "l", "zzzzzz"] {
+            for max in 0..4 {
+                let mut expected: Vec<_> = words.iter()
+                    .map(|w| (w.to_string(), damerau_levenshtein(target, w)))
+                    .filter(|(_, d)| *d <= max)
+                    .collect();
+                expected.sort();
+                let mut actual = tree.find(target, max);
+                actual.sort();
+                assert_eq!(actual, expected, "{} within {}", target, max);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_refresh_reuses_unchanged_dirs() {
+        let dir = tempdir().unwrap();
+        create_executable(dir.path(), "cargo");
+        File::create(dir.path().join("car")).unwrap();
+        // Outside the indexed directory, so saving doesn't change its mtime
+        let cache_dir = tempdir().unwrap();
+        let cache = cache_dir.path().join("ffs").join("executables.toml");
+
+        let mut index = ExecutableIndex::load(&cache);
+        index.refresh(&[dir.path().to_path_buf()]);
+        assert!(index.contains("cargo"));
+        assert!(!index.contains("car"));
+        assert_eq!(index.find_similar("carg", 3), vec![("cargo".to_string(), 1)]);
+        index.save(&cache).unwrap();
+
+        // Unchanged: the listing comes from the cache, not the directory
+        let mut index = ExecutableIndex::load(&cache);
+        index.dirs[0].names.push("cached-only".to_string());
+        index.refresh(&[dir.path().to_path_buf()]);
+        assert!(!index.dirty);
+        assert!(index.contains("cached-only"));
+
+        // Changed: re-listed
+        index.dirs[0].mtime = 0;
+        index.refresh(&[dir.path().to_path_buf()]);
+        assert!(index.dirty);
+        assert!(!index.contains("cached-only"));
+
+        // Dropped from PATH
+        index.refresh(&[]);
+        assert!(!index.contains("cargo"));
+    }
+}
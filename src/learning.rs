@@ -0,0 +1,166 @@
+//! Learns which corrections the user picks, so they rank higher next time the
+//! same kind of command fails.
+//!
+//! Choices are keyed by the *shape* of the failed command and of the
+//! correction: the program and, if it looks like one, its subcommand. Each
+//! pick adds one to a score that halves every [`HALF_LIFE_DAYS`].
+
+use crate::shells::unix_timestamp;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const HALF_LIFE_DAYS: f64 = 30.0;
+/// Priority added per unit of score.
+const BOOST_PER_PICK: f64 = 100.0;
+const MAX_BOOST: f64 = 500.0;
+/// Choices whose score decayed below this are dropped on save.
+const MIN_SCORE: f64 = 0.01;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Choice {
+    command: String,
+    correction: String,
+    score: f64,
+    last_used: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ChoicesFile {
+    #[serde(default)]
+    choice: Vec<Choice>,
+}
+
+#[derive(Debug)]
+pub struct Preferences {
+    path: PathBuf,
+    choices: Vec<Choice>,
+}
+
+impl Preferences {
+    pub fn default_path() -> PathBuf {
+        let data_dir = dirs::data_dir().unwrap_or_else(|| PathBuf::from("."));
+        data_dir.join("ffs").join("choices.toml")
+    }
+
+    /// Loads the choices at `path`; a missing or unreadable file means none.
+    pub fn load(path: &Path) -> Self {
+        let choices = fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| toml::from_str::<ChoicesFile>(&contents).ok())
+            .unwrap_or_default()
+            .choice;
+        Self { path: path.to_path_buf(), choices }
+    }
+
+    /// Priority to add to `correction` when fixing `script`.
+    pub fn boost(&self, script: &str, correction: &str) -> usize {
+        self.boost_at(script, correction, unix_timestamp())
+    }
+
+    fn boost_at(&self, script: &str, correction: &str, now: u64) -> usize {
+        let (command, correction) = (shape(script), shape(correction));
+        self.choices.iter()
+            .find(|c| c.command == command && c.correction == correction)
+            .map_or(0, |c| (decayed(c.score, c.last_used, now) * BOOST_PER_PICK).min(MAX_BOOST) as usize)
+    }
+
+    /// Records that the user fixed `script` with `correction` and saves.
+    pub fn record(&mut self, script: &str, correction: &str) -> Result<()> {
+        self.record_at(script, correction, unix_timestamp());
+        self.save()
+    }
+
+    fn record_at(&mut self, script: &str, correction: &str, now: u64) {
+        let (command, correction) = (shape(script), shape(correction));
+        match self.choices.iter_mut().find(|c| c.command == command && c.correction == correction) {
+            Some(choice) => {
+                choice.score = decayed(choice.score, choice.last_used, now) + 1.0;
+                choice.last_used = now;
+            }
+            None => self.choices.push(Choice { command, correction, score: 1.0, last_used: now }),
+        }
+    }
+
+    fn save(&mut self) -> Result<()> {
+        let now = unix_timestamp();
+        self.choices.retain(|c| decayed(c.score, c.last_used, now) >= MIN_SCORE);
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = ChoicesFile { choice: self.choices.clone() };
+        fs::write(&self.path, toml::to_string(&file)?)?;
+        Ok(())
+    }
+
+    /// Forgets every choice.
+    pub fn clear(path: &Path) -> Result<()> {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn decayed(score: f64, last_used: u64, now: u64) -> f64 {
+    let days = now.saturating_sub(last_used) as f64 / 86_400.0;
+    score * 0.5f64.powf(days / HALF_LIFE_DAYS)
+}
+
+/// The program and, when the next word looks like a subcommand rather than a
+/// flag or path, that word: `git psuh origin main` is `git psuh`.
+fn shape(script: &str) -> String {
+    let mut words = script.split_whitespace().skip_while(|w| *w == "sudo");
+    let program = words.next().unwrap_or_default();
+    match words.next() {
+        Some(word) if word.chars().all(|c| c.is_ascii_lowercase() || c == '-') && !word.starts_with('-') => {
+            format!("{} {}", program, word)
+        }
+        _ => program.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const DAY: u64 = 86_400;
+
+    #[test]
+    fn test_shape() {
+        assert_eq!(shape("git psuh origin main"), "git psuh");
+        assert_eq!(shape("sudo apt-get instal vim"), "apt-get instal");
+        assert_eq!(shape("mkdir /tmp/a/b"), "mkdir");
+        assert_eq!(shape("ls -la"), "ls");
+    }
+
+    #[test]
+    fn test_boost_decays() {
+        let mut prefs = Preferences { path: PathBuf::new(), choices: vec![] };
+        let start = 1_700_000_000;
+        prefs.record_at("git psuh origin", "git push origin", start);
+        prefs.record_at("git psuh", "git push", start);
+
+        assert_eq!(prefs.boost_at("git psuh upstream", "git push upstream", start), 200);
+        assert_eq!(prefs.boost_at("git psuh", "git push", start + 30 * DAY), 100);
+        assert_eq!(prefs.boost_at("git psuh", "git pull", start), 0);
+        assert_eq!(prefs.boost_at("git comit", "git push", start), 0);
+    }
+
+    #[test]
+    fn test_record_load_and_clear() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("ffs").join("choices.toml");
+
+        let mut prefs = Preferences::load(&path);
+        prefs.record("git psuh", "git push").unwrap();
+        assert!(Preferences::load(&path).boost("git psuh", "git push") > 0);
+
+        Preferences::clear(&path).unwrap();
+        assert_eq!(Preferences::load(&path).boost("git psuh", "git push"), 0);
+        Preferences::clear(&path).unwrap();
+    }
+}
@@ -6,3 +6,13 @@ pub mod config;
 pub mod ui;
 pub mod utils;
 pub mod scripting;
+pub mod paths;
+pub mod declarative;
+pub mod testing;
+pub mod plugin;
+pub mod setup;
+pub mod learning;
+pub mod executables;
+pub mod typo;
+pub mod output;
+pub mod messages;
@@ -1,8 +1,9 @@
 use clap::{Parser, Subcommand};
 use ffs::types::Command;
-use ffs::config::load_config;
-use ffs::engine::Engine;
-use ffs::shells::{Shell, Bash, Fish, Zsh};
+use ffs::config::{load_config, Config};
+use ffs::messages::english_locale;
+use ffs::engine::{Engine, Evaluation};
+use ffs::shells::{AliasOptions, Bash, Shell, append_to_history, detect_running_shell, detect_shell, is_valid_alias_name, shell_from_name};
 use ffs::rules::{
     cargo::CargoRule,
     git::{GitCheckout, GitPush, GitNoCommand},
@@ -11,10 +12,21 @@ use ffs::rules::{
     sudo::Sudo,
     cd::CdMkdir,
     python::{PythonExecute, PipUnknownCommand},
+    history::History,
+    did_you_mean::DidYouMean,
+    unknown_argument::UnknownArgument,
 };
 use ffs::scripting::load_rhai_rules;
-use ffs::ui::select_correction;
-use ffs::utils::get_last_command;
+use ffs::executables;
+use ffs::declarative::{build_declarative_rules, load_declarative_rules};
+use ffs::plugin::load_wasm_rules;
+use ffs::learning::Preferences;
+use ffs::typo::{command_frequencies, DefaultScorer, DEFAULT_MAX_DISTANCE, DEFAULT_MIN_SIMILARITY};
+use ffs::setup::{install, is_configured, SetupOutcome};
+use ffs::paths::{rule_dirs, RuleDir, TrustStore};
+use ffs::testing::{discover_test_files, run_test_file, report};
+use ffs::ui::{confirm_trust, select_correction, select_streamed_correction, Selection};
+use ffs::utils::{get_last_command, get_logged_output};
 use std::sync::Arc;
 use std::process::{Command as SysCommand, Stdio};
 use anyhow::{Result, anyhow};
@@ -24,105 +36,417 @@ use std::path::PathBuf;
 #[derive(Parser)]
 #[command(name = "ffs")]
 #[command(about = "Magnificent app which corrects your previous console command", long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    /// Print the shell function; the shell is detected when not given
+    #[arg(short, long, value_name = "SHELL")]
+    alias: Option<Option<String>>,
+
+    /// Print an Esc Esc binding that puts the correction in the line editor
+    #[arg(long, value_name = "SHELL", conflicts_with = "alias")]
+    keybinding: Option<Option<String>>,
+
+    /// Name of the function printed by `--alias`, and of `TF_ALIAS`
+    #[arg(long, value_name = "NAME", default_value = "ffs")]
+    alias_name: String,
+
+    /// Run the first correction without asking
+    #[arg(short, long, alias = "yes")]
+    yeah: bool,
+
+    /// Offer to fix the correction as well if it fails
     #[arg(short, long)]
-    alias: Option<String>,
+    repeat: bool,
+
+    /// Read the failed command's output from the session log instead of re-running it
+    #[arg(long)]
+    instant: bool,
+
+    /// Print rule timings and timeouts
+    #[arg(long)]
+    debug: bool,
+
+    /// Fix this command instead of the last one in history
+    #[arg(long, value_name = "COMMAND", hide = true)]
+    force_command: Option<String>,
+
+    /// Don't add the correction to the history file; set by the keybindings,
+    /// which only put it in the line editor
+    #[arg(long, hide = true)]
+    no_history: bool,
 
     /// Command arguments (captured when used as alias)
     #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
     args: Vec<String>,
 }
 
+#[derive(Subcommand)]
+enum Commands {
+    /// Manage user rules
+    Rules {
+        #[command(subcommand)]
+        command: RulesCommand,
+    },
+    /// Manage what ffs has learned from the corrections you picked
+    History {
+        #[command(subcommand)]
+        command: HistoryCommand,
+    },
+    /// Add the line loading the shell function to your shell's startup file
+    Setup {
+        /// The shell to set up (detected when not given)
+        shell: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum HistoryCommand {
+    /// Forget every picked correction
+    Clear,
+}
+
+#[derive(Subcommand)]
+enum RulesCommand {
+    /// Run the `*.test.toml` cases next to each `.rhai` rule
+    Test {
+        /// Directories to search (defaults to the rule search path, with
+        /// project rules only if trusted)
+        dirs: Vec<PathBuf>,
+    },
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    match cli.command {
+        Some(Commands::Rules { command: RulesCommand::Test { dirs } }) => {
+            let dirs = if dirs.is_empty() {
+                let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+                trusted_rule_dirs(rule_dirs(&cwd))
+            } else {
+                dirs
+            };
+
+            let results: Vec<_> = discover_test_files(&dirs).iter().flat_map(|f| run_test_file(f)).collect();
+            if !report(&results) {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Commands::History { command: HistoryCommand::Clear }) => {
+            Preferences::clear(&Preferences::default_path())?;
+            eprintln!("Forgot all picked corrections.");
+            return Ok(());
+        }
+        Some(Commands::Setup { shell }) => return setup(shell_for(shell, "ffs setup")?.as_ref()),
+        None => {}
+    }
+
     // 1. Alias Generation
+    if (cli.keybinding.is_some() || cli.alias.is_some()) && !is_valid_alias_name(&cli.alias_name) {
+        return Err(anyhow!("Invalid alias name: {}", cli.alias_name));
+    }
+
+    if let Some(shell_name) = cli.keybinding {
+        let shell = shell_for(shell_name, "--keybinding")?;
+        let binding = shell.keybinding(&cli.alias_name)
+            .ok_or_else(|| anyhow!("Keybindings are not supported for {}", shell.name()))?;
+        println!("{}", binding);
+        return Ok(());
+    }
+
     if let Some(shell_name) = cli.alias {
-        let shell: Box<dyn Shell> = match shell_name.as_str() {
-            "bash" => Box::new(Bash),
-            "fish" => Box::new(Fish),
-            "zsh" => Box::new(Zsh),
-            _ => return Err(anyhow!("Unsupported shell: {}", shell_name)),
+        let shell = shell_for(shell_name, "--alias")?;
+        let config = load_config()?;
+        let options = AliasOptions {
+            alter_history: config.alter_history.unwrap_or(true),
+            yeah: cli.yeah,
+            repeat: cli.repeat,
+            instant: cli.instant,
         };
-        println!("{}", shell.app_alias("ffs")); // Or 'fuck' if user wants
+        println!("{}", shell.app_alias(&cli.alias_name, &options));
         return Ok(());
     }
 
     // 2. Normal Operation (Fix Command)
+    let shell = detect_shell();
+    // Built while the command is re-run, and waited for before the rules start
+    let executables_ready = executables::warm_up();
 
-    // Step A: Get the Last Command
-    let script = match get_last_command() {
+    // Without TF_HISTORY ffs wasn't called through the shell function
+    if cli.force_command.is_none() && std::env::var_os("TF_HISTORY").is_none() {
+        warn_not_configured(shell.as_deref());
+    }
+
+    // Step A: Get the Last Command, with any shell alias in it expanded
+    let typed_script = match cli.force_command.clone().or_else(|| get_last_command(shell.as_deref())) {
         Some(s) => s,
         None => {
-            eprintln!("No previous command found in TF_HISTORY.");
+            eprintln!("No previous command found in TF_HISTORY or the shell's history file.");
             return Ok(());
         }
     };
+    let script = match &shell {
+        Some(shell) => shell.expand_aliases(&typed_script),
+        None => typed_script.clone(),
+    };
 
-    // Step B: Re-run the failed command to capture output (stdout/stderr)
-    eprintln!("{}", format!("Re-running: {}", script).dimmed());
-
-    let output = SysCommand::new("sh")
-        .arg("-c")
-        .arg(&script)
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output();
+    let config = load_config()?;
 
-    let (stdout, stderr) = match output {
-        Ok(o) => (
-            String::from_utf8_lossy(&o.stdout).to_string(),
-            String::from_utf8_lossy(&o.stderr).to_string()
-        ),
-        Err(e) => {
-            eprintln!("Failed to re-run command: {}", e);
-            return Ok(());
+    // Step B: Take the output from the session log in instant mode, otherwise
+    // re-run the failed command to capture it
+    let logged = if cli.instant { get_logged_output(&typed_script) } else { None };
+    let (stdout, stderr, exit_code) = match logged {
+        // The log interleaves both streams, so rules see it on either
+        Some(output) => {
+            let exit_code = std::env::var("TF_EXIT_CODE").ok().and_then(|c| c.parse().ok());
+            (output.clone(), output, exit_code)
         }
+        None => match rerun(shell.as_deref(), &script, rerun_locale(&config).as_deref()) {
+            Some(output) => output,
+            None => return Ok(()),
+        },
     };
 
-    let command = Command::new(script, stdout, stderr);
+    let command = Command::from_output(script, stdout, stderr).with_exit_code(exit_code);
 
     // Step C: Initialize Engine & Load Rules
-    let config = load_config()?;
+    let alter_history = config.alter_history.unwrap_or(true) && !cli.no_history;
+    let yeah = cli.yeah || !config.require_confirmation.unwrap_or(true);
+    let debug = cli.debug || config.debug.unwrap_or(false);
+    let learn = config.learn.unwrap_or(true);
+    let history_limit = config.history_limit.unwrap_or(100);
+    let scorer = DefaultScorer::new(
+        config.typo_max_distance.unwrap_or(DEFAULT_MAX_DISTANCE),
+        config.typo_min_similarity.unwrap_or(DEFAULT_MIN_SIMILARITY),
+    );
+    let config_rules = build_declarative_rules(config.rule.as_deref().unwrap_or_default());
     let mut engine = Engine::new(config);
+    engine.set_preferences(Preferences::load(&Preferences::default_path()));
 
     // Register builtin rules
     engine.register_rule(Arc::new(CargoRule));
     engine.register_rule(Arc::new(GitCheckout));
     engine.register_rule(Arc::new(GitPush));
     engine.register_rule(Arc::new(GitNoCommand));
-    engine.register_rule(Arc::new(UnknownCommand));
+    let (shell_commands, mut history) = match &shell {
+        Some(shell) => (shell.get_aliases().into_keys().chain(shell.get_functions()).collect(), shell.get_history()),
+        None => (Vec::new(), Vec::new()),
+    };
+    history.drain(..history.len().saturating_sub(history_limit));
+    let scorer = scorer.with_frequencies(command_frequencies(&history));
+    engine.register_rule(Arc::new(UnknownCommand::new(shell_commands).with_scorer(scorer.clone())));
     engine.register_rule(Arc::new(MkdirP));
     engine.register_rule(Arc::new(Sudo));
     engine.register_rule(Arc::new(CdMkdir));
     engine.register_rule(Arc::new(PythonExecute));
     engine.register_rule(Arc::new(PipUnknownCommand));
+    engine.register_rule(Arc::new(DidYouMean));
+    engine.register_rule(Arc::new(UnknownArgument::default().with_scorer(scorer)));
+    if shell.is_some() {
+        engine.register_rule(Arc::new(History::new(history, std::env::var("TF_ALIAS").ok().as_deref())));
+    }
 
-    // Load Rhai rules
-    // Use XDG config home usually, or ~/.config/ffs/rules
-    let config_dir = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
-    let rules_dir = config_dir.join("ffs").join("rules");
+    // Load scripted rules from every rule directory, closest to the project last
+    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let rule_dirs = trusted_rule_dirs(rule_dirs(&cwd));
 
-    if rules_dir.exists() {
-        let rhai_rules = load_rhai_rules(&rules_dir);
-        for rule in rhai_rules {
-            engine.register_rule(Arc::new(rule));
-        }
+    for rule in load_rhai_rules(&rule_dirs) {
+        engine.register_rule(Arc::new(rule));
+    }
+    for rule in load_wasm_rules(&rule_dirs) {
+        engine.register_rule(Arc::new(rule));
     }
 
-    // Step D: Get Corrections
-    let corrections = engine.get_corrections(&command);
+    // Declarative rules from config.toml and *.toml files in the rule directories
+    for rule in config_rules.into_iter().chain(load_declarative_rules(&rule_dirs)) {
+        engine.register_rule(Arc::new(rule));
+    }
 
-    if corrections.is_empty() {
-        eprintln!("No corrections found.");
-        return Ok(());
+    let _ = executables_ready.join();
+
+    // Step D/E: Get Corrections and Select one. Interactively the list is shown
+    // while slower rules are still running; otherwise wait for all of them so
+    // the best correction is known.
+    let selection = if yeah || debug {
+        let evaluation = engine.evaluate(&command);
+        if debug {
+            print_evaluation(&evaluation);
+        }
+        let corrections = evaluation.corrections;
+        if corrections.is_empty() {
+            Selection::NoCorrections
+        } else if yeah {
+            Selection::Chosen(corrections[0].clone())
+        } else {
+            select_correction(&corrections).cloned().map_or(Selection::Cancelled, Selection::Chosen)
+        }
+    } else {
+        select_streamed_correction(engine.stream(&command))
+    };
+
+    let correction = match selection {
+        Selection::Chosen(correction) => correction,
+        Selection::Cancelled => return Ok(()),
+        Selection::NoCorrections => {
+            eprintln!("No corrections found.");
+            return Ok(());
+        }
+    };
+
+    if learn && !yeah {
+        let mut preferences = Preferences::load(&Preferences::default_path());
+        if let Err(e) = preferences.record(&command.script, &correction.command) {
+            eprintln!("Failed to save picked correction: {}", e);
+        }
+    }
+
+    if let Some(shell) = shell.as_ref().filter(|s| alter_history && s.writes_history_file()) {
+        if let Err(e) = append_to_history(shell.as_ref(), &correction.command) {
+            eprintln!("Failed to update history file: {}", e);
+        }
     }
 
-    // Step E: Select Correction
-    if let Some(correction) = select_correction(&corrections) {
+    // With --yeah nobody is asked, so only one repeat is chained to avoid
+    // looping on corrections that fail the same way
+    if cli.repeat && !(yeah && cli.force_command.is_some()) {
+        let quoting: &dyn Shell = shell.as_deref().unwrap_or(&Bash);
+        let alias = std::env::var("TF_ALIAS").unwrap_or_else(|_| "ffs".to_string());
+        let again = format!("{} --repeat --force-command {}", alias, quoting.quote(&correction.command));
+        print!("{}", quoting.or(&correction.command, &again));
+    } else {
         print!("{}", correction.command);
     }
 
     Ok(())
 }
+
+fn print_evaluation(evaluation: &Evaluation) {
+    for (rule, elapsed) in &evaluation.timings {
+        eprintln!("{}", format!("DEBUG: {} took {}ms", rule, elapsed.as_millis()).dimmed());
+    }
+    for rule in &evaluation.timed_out {
+        eprintln!("{}", format!("DEBUG: {} timed out, its corrections were dropped", rule).yellow());
+    }
+    for rule in &evaluation.panicked {
+        eprintln!("{}", format!("DEBUG: {} panicked", rule).red());
+    }
+}
+
+/// Appends the function to the shell's startup file.
+fn setup(shell: &dyn Shell) -> Result<()> {
+    let config = shell.how_to_configure().ok_or_else(|| anyhow!(
+        "Automatic setup is not supported for {0}, add the output of `ffs --alias {0}` to its startup file",
+        shell.name()
+    ))?;
+
+    match install(&config)? {
+        SetupOutcome::AlreadyConfigured => {
+            eprintln!("{} already loads ffs.", config.path.display());
+        }
+        SetupOutcome::Installed { backup } => {
+            eprintln!("Added `{}` to {}.", config.content, config.path.display());
+            if let Some(backup) = backup {
+                eprintln!("The previous version was saved to {}.", backup.display());
+            }
+            eprintln!("Restart your shell, or run `source {}`, to use it.", config.path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Explains how to load the shell function, which lets ffs see the session's
+/// history and run the correction.
+fn warn_not_configured(shell: Option<&dyn Shell>) {
+    let config = shell.and_then(|s| s.how_to_configure());
+    let message = match &config {
+        Some(config) if is_configured(&config.path) => format!(
+            "ffs is set up in {} but not loaded in this shell; restart it or run `source {}`.",
+            config.path.display(),
+            config.path.display()
+        ),
+        Some(config) => format!(
+            "ffs isn't set up for this shell. Run `ffs setup` to add `{}` to {}, or add it yourself.",
+            config.content,
+            config.path.display()
+        ),
+        None => "ffs isn't set up for this shell. Add the output of `ffs --alias` to its startup file.".to_string(),
+    };
+    eprintln!("{}", message.yellow());
+}
+
+/// The locale to re-run commands in, or `None` to keep the user's.
+fn rerun_locale(config: &Config) -> Option<String> {
+    if !config.force_locale.unwrap_or(true) {
+        return None;
+    }
+    Some(config.rerun_locale.clone().unwrap_or_else(|| english_locale().to_string()))
+}
+
+/// Re-runs `script` and captures its stdout, stderr and exit code, in `locale`
+/// if given so the messages rules match are in English.
+fn rerun(shell: Option<&dyn Shell>, script: &str, locale: Option<&str>) -> Option<(String, String, Option<i32>)> {
+    eprintln!("{}", format!("Re-running: {}", script).dimmed());
+
+    let mut rerun = match shell {
+        Some(shell) => shell.rerun_command(script),
+        None => {
+            let mut cmd = SysCommand::new("sh");
+            cmd.arg("-c").arg(script);
+            cmd
+        }
+    };
+    if let Some(locale) = locale {
+        // GNU gettext prefers LANGUAGE over LC_ALL
+        rerun.env("LC_ALL", locale).env("LANG", locale).env_remove("LANGUAGE");
+    }
+    let output = rerun
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output();
+
+    match output {
+        Ok(o) => Some((
+            String::from_utf8_lossy(&o.stdout).to_string(),
+            String::from_utf8_lossy(&o.stderr).to_string(),
+            o.status.code(),
+        )),
+        Err(e) => {
+            eprintln!("Failed to re-run command: {}", e);
+            None
+        }
+    }
+}
+
+/// The shell named on the command line, or the detected one.
+fn shell_for(name: Option<String>, flag: &str) -> Result<Box<dyn Shell>> {
+    match name {
+        Some(name) => shell_from_name(&name).ok_or_else(|| anyhow!("Unsupported shell: {}", name)),
+        None => detect_running_shell().ok_or_else(|| anyhow!("Could not detect your shell, pass it as `{} <shell>`", flag)),
+    }
+}
+
+/// Drops project-local rule directories the user hasn't agreed to run.
+fn trusted_rule_dirs(dirs: Vec<RuleDir>) -> Vec<PathBuf> {
+    let mut store = TrustStore::load(&TrustStore::default_path());
+
+    dirs.into_iter()
+        .filter(|dir| {
+            if !dir.project_local || store.is_trusted(&dir.path) {
+                return true;
+            }
+            if confirm_trust(&dir.path) {
+                if let Err(e) = store.trust(&dir.path) {
+                    eprintln!("Failed to save trusted rules directory: {}", e);
+                }
+                return true;
+            }
+            false
+        })
+        .map(|dir| dir.path)
+        .collect()
+}
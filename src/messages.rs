@@ -0,0 +1,128 @@
+//! Translations of the system messages rules look for. Commands are re-run
+//! in an English locale, but output can still be localized when
+//! `force_locale` is off or comes from the instant-mode log, so rules match
+//! through here.
+
+use once_cell::sync::Lazy;
+use std::path::Path;
+
+/// Where glibc keeps compiled locales.
+const LOCALE_DIRS: &[&str] = &["/usr/lib/locale"];
+
+/// `C.UTF-8` where the system has it, so output stays UTF-8 while messages
+/// are in English, otherwise `C`.
+pub fn english_locale() -> &'static str {
+    static LOCALE: Lazy<&'static str> = Lazy::new(|| pick_english_locale(LOCALE_DIRS));
+    &LOCALE
+}
+
+/// Looks for the locale in `dirs` rather than asking `locale -a`, which
+/// would cost a process per run. It's spelled `C.utf8` or `C.UTF-8`.
+fn pick_english_locale<P: AsRef<Path>>(dirs: &[P]) -> &'static str {
+    let has_utf8 = dirs.iter()
+        .any(|d| ["C.utf8", "C.UTF-8"].iter().any(|n| d.as_ref().join(n).exists()));
+    if has_utf8 { "C.UTF-8" } else { "C" }
+}
+
+/// A message printed by the C library or the shell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    NoSuchFile,
+    PermissionDenied,
+    CommandNotFound,
+}
+
+impl Message {
+    /// The message named `name` in snake case, as scripted rules pass it.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "no_such_file" => Some(Self::NoSuchFile),
+            "permission_denied" => Some(Self::PermissionDenied),
+            "command_not_found" => Some(Self::CommandNotFound),
+            _ => None,
+        }
+    }
+
+    /// The English text followed by its translations (de, fr, es, it, pt,
+    /// nl, ru, pl, ja, zh).
+    pub fn variants(self) -> &'static [&'static str] {
+        match self {
+            Self::NoSuchFile => &[
+                "No such file or directory",
+                "Datei oder Verzeichnis nicht gefunden",
+                "Aucun fichier ou dossier de ce type",
+                "No existe el archivo o el directorio",
+                "File o directory non esistente",
+                "Arquivo ou diretório inexistente",
+                "Bestand of map bestaat niet",
+                "Нет такого файла или каталога",
+                "Nie ma takiego pliku ani katalogu",
+                "そのようなファイルやディレクトリはありません",
+                "没有那个文件或目录",
+            ],
+            Self::PermissionDenied => &[
+                "Permission denied",
+                "Keine Berechtigung",
+                "Permission non accordée",
+                "Permiso denegado",
+                "Permesso negato",
+                "Permissão negada",
+                "Toegang geweigerd",
+                "Отказано в доступе",
+                "Brak dostępu",
+                "許可がありません",
+                "权限不够",
+            ],
+            Self::CommandNotFound => &[
+                "command not found",
+                "Befehl nicht gefunden",
+                "commande introuvable",
+                "no se encontró la orden",
+                "comando non trovato",
+                "comando não encontrado",
+                "opdracht niet gevonden",
+                "команда не найдена",
+                "nie znaleziono polecenia",
+                "コマンドが見つかりません",
+                "未找到命令",
+            ],
+        }
+    }
+}
+
+/// Whether `text` contains `message` in any known language, ignoring case.
+pub fn contains(text: &str, message: Message) -> bool {
+    let text = text.to_lowercase();
+    message.variants().iter().any(|variant| text.contains(&variant.to_lowercase()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contains_translations() {
+        assert!(contains("mkdir: cannot create directory 'a/b': No such file or directory", Message::NoSuchFile));
+        assert!(contains("mkdir: kann Verzeichnis 'a/b' nicht anlegen: Datei oder Verzeichnis nicht gefunden", Message::NoSuchFile));
+        assert!(contains("touch: невозможно выполнить touch '/x': ОТКАЗАНО В ДОСТУПЕ", Message::PermissionDenied));
+        assert!(contains("bash: gti : commande introuvable", Message::CommandNotFound));
+        assert!(!contains("bash: gti : commande introuvable", Message::NoSuchFile));
+    }
+
+    #[test]
+    fn test_pick_english_locale() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("en_US.utf8")).unwrap();
+        assert_eq!(pick_english_locale(&[dir.path()]), "C");
+        assert_eq!(pick_english_locale::<&Path>(&[]), "C");
+
+        std::fs::create_dir(dir.path().join("C.utf8")).unwrap();
+        assert_eq!(pick_english_locale(&[dir.path()]), "C.UTF-8");
+    }
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(Message::from_name("permission_denied"), Some(Message::PermissionDenied));
+        assert_eq!(Message::from_name("PermissionDenied"), None);
+    }
+}
@@ -0,0 +1,125 @@
+//! Cleanup of command output before rules see it. Colors, terminal titles
+//! and progress bars redrawn with `\r` get in the way of matching messages,
+//! even when the tool's output is piped.
+
+/// Bytes of output kept for matching; the middle of longer output is dropped.
+pub const MAX_OUTPUT: usize = 64 * 1024;
+
+/// `raw` without escape sequences, with `\r\n` line endings and `\r`-redrawn
+/// lines reduced to what ends up on screen, and at most [`MAX_OUTPUT`] bytes.
+pub fn normalize(raw: &str) -> String {
+    truncate(&collapse_carriage_returns(&strip_escapes(raw)), MAX_OUTPUT)
+}
+
+/// Removes ANSI/VT escape sequences (CSI, such as colors; OSC, such as titles
+/// and hyperlinks; DCS and the like) and applies backspaces.
+pub fn strip_escapes(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut chars = raw.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                Some('[') => skip_csi(&mut chars),
+                Some(']' | 'P' | 'X' | '^' | '_') => skip_string(&mut chars),
+                // Intermediate bytes then a final one, e.g. `ESC ( B`
+                Some(' '..='/') => {
+                    while chars.next_if(|c| matches!(c, ' '..='/')).is_some() {}
+                    chars.next();
+                }
+                _ => {}
+            },
+            '\u{9b}' => skip_csi(&mut chars),
+            // Overstriking, as in man pages: `b\x08b` is a bold `b`
+            '\x08' => {
+                out.pop();
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn skip_csi(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    for c in chars.by_ref() {
+        if matches!(c, '@'..='~') {
+            break;
+        }
+    }
+}
+
+/// Skips to the string terminator, `ESC \` or BEL.
+fn skip_string(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    while let Some(c) = chars.next() {
+        if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+            break;
+        }
+    }
+}
+
+/// Turns `\r\n` into `\n` and keeps only the last redraw of each line, so
+/// `10%\r50%\r100%` is `100%`.
+pub fn collapse_carriage_returns(text: &str) -> String {
+    text.replace("\r\n", "\n")
+        .split('\n')
+        .map(|line| line.rsplit('\r').find(|s| !s.is_empty()).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The start and end of `text` with the middle replaced by a marker if it's
+/// longer than `max` bytes. Errors tend to be at either end.
+pub fn truncate(text: &str, max: usize) -> String {
+    if text.len() <= max {
+        return text.to_string();
+    }
+    let mut head = max / 2;
+    while !text.is_char_boundary(head) {
+        head -= 1;
+    }
+    let mut tail = text.len() - max / 2;
+    while !text.is_char_boundary(tail) {
+        tail += 1;
+    }
+    format!("{}\n[... {} bytes truncated ...]\n{}", &text[..head], tail - head, &text[tail..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_escapes() {
+        assert_eq!(strip_escapes("\x1b[1m\x1b[31merror\x1b[0m: Did you mean `build`?"), "error: Did you mean `build`?");
+        assert_eq!(strip_escapes("\x1b]0;title\x07prompt"), "prompt");
+        assert_eq!(strip_escapes("\x1b]8;;https://example.com\x1b\\link\x1b]8;;\x1b\\"), "link");
+        assert_eq!(strip_escapes("\x1b(Bplain\x1b=\x1b[?25l"), "plain");
+        assert_eq!(strip_escapes("N\x08NA\x08AM\x08ME\x08E"), "NAME");
+        assert_eq!(strip_escapes("naïve ✔"), "naïve ✔");
+    }
+
+    #[test]
+    fn test_collapse_carriage_returns() {
+        assert_eq!(collapse_carriage_returns("a\r\nb\r\n"), "a\nb\n");
+        assert_eq!(collapse_carriage_returns("Downloading 10%\rDownloading 100%\r\nerror: failed"), "Downloading 100%\nerror: failed");
+        assert_eq!(collapse_carriage_returns("done\r"), "done");
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("short", 10), "short");
+        let text = format!("{}{}", "é".repeat(10), "z".repeat(10));
+        let truncated = truncate(&text, 10);
+        assert!(truncated.starts_with("éé\n[... "));
+        assert!(truncated.ends_with(" bytes truncated ...]\nzzzzz"));
+    }
+
+    #[test]
+    fn test_raw_output_is_capped() {
+        let stdout = format!("\x1b[31m{}", "x".repeat(2 * MAX_OUTPUT));
+        let command = crate::types::Command::from_output("yes".to_string(), stdout, String::new());
+        assert!(command.raw_stdout.starts_with("\x1b[31m"));
+        assert!(command.raw_stdout.len() < MAX_OUTPUT + 64);
+        assert!(command.stdout.len() < MAX_OUTPUT + 64);
+    }
+}
@@ -0,0 +1,242 @@
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use sha2::{Digest, Sha256};
+
+pub const SYSTEM_RULES_DIR: &str = "/etc/ffs/rules";
+pub const PROJECT_RULES_DIR: &str = ".ffs/rules";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleDir {
+    pub path: PathBuf,
+    /// Checked into a repository rather than installed by the user, so its
+    /// scripts must be trusted before they run.
+    pub project_local: bool,
+}
+
+impl RuleDir {
+    fn new(path: PathBuf, project_local: bool) -> Self {
+        Self { path, project_local }
+    }
+}
+
+pub fn user_rules_dir() -> PathBuf {
+    let config_dir = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
+    config_dir.join("ffs").join("rules")
+}
+
+/// Rule directories in increasing order of precedence: system-wide, user
+/// config, each entry of `FFS_RULES_PATH`, then the nearest `.ffs/rules` found
+/// walking up from `cwd`. Directories that don't exist are left out.
+pub fn rule_dirs(cwd: &Path) -> Vec<RuleDir> {
+    rule_dirs_from(
+        Path::new(SYSTEM_RULES_DIR),
+        &user_rules_dir(),
+        env::var_os("FFS_RULES_PATH"),
+        cwd,
+    )
+}
+
+pub fn rule_dirs_from(system: &Path, user: &Path, rules_path: Option<OsString>, cwd: &Path) -> Vec<RuleDir> {
+    let mut dirs = vec![
+        RuleDir::new(system.to_path_buf(), false),
+        RuleDir::new(user.to_path_buf(), false),
+    ];
+
+    if let Some(rules_path) = rules_path {
+        dirs.extend(env::split_paths(&rules_path).map(|p| RuleDir::new(p, false)));
+    }
+
+    if let Some(project) = find_project_rules_dir(cwd) {
+        dirs.push(RuleDir::new(project, true));
+    }
+
+    let mut seen = BTreeSet::new();
+    dirs.retain(|d| d.path.is_dir() && seen.insert(d.path.clone()));
+    dirs
+}
+
+pub fn find_project_rules_dir(cwd: &Path) -> Option<PathBuf> {
+    cwd.ancestors()
+        .map(|dir| dir.join(PROJECT_RULES_DIR))
+        .find(|dir| dir.is_dir())
+}
+
+/// Files with extension `ext` across `dirs`. A file in a later directory
+/// shadows one with the same name in an earlier directory; the result is
+/// sorted by file name so load order doesn't depend on `read_dir`.
+pub fn rule_files(dirs: &[PathBuf], ext: &str) -> Vec<PathBuf> {
+    let mut files = BTreeMap::new();
+
+    for dir in dirs {
+        if let Ok(entries) = fs::read_dir(dir) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.is_file() && path.extension().is_some_and(|e| e == ext) {
+                    files.insert(entry.file_name(), path);
+                }
+            }
+        }
+    }
+
+    files.into_values().collect()
+}
+
+/// Project rule directories the user has agreed to run scripts from, each with
+/// a hash of its contents when trusted so changed rules are asked about again.
+#[derive(Debug, Default)]
+pub struct TrustStore {
+    path: PathBuf,
+    dirs: BTreeMap<PathBuf, String>,
+}
+
+impl TrustStore {
+    pub fn default_path() -> PathBuf {
+        let data_dir = dirs::data_dir().unwrap_or_else(|| PathBuf::from("."));
+        data_dir.join("ffs").join("trusted_dirs")
+    }
+
+    /// Reads `<hash> <path>` lines. Lines without a hash, as older versions
+    /// wrote them, are dropped so those directories are asked about again.
+    pub fn load(path: &Path) -> Self {
+        let dirs = fs::read_to_string(path)
+            .map(|contents| {
+                contents.lines()
+                    .filter_map(|l| l.split_once(' '))
+                    .filter(|(hash, _)| hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit()))
+                    .map(|(hash, dir)| (PathBuf::from(dir), hash.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { path: path.to_path_buf(), dirs }
+    }
+
+    /// Whether `dir` was trusted and its files haven't changed since.
+    pub fn is_trusted(&self, dir: &Path) -> bool {
+        self.dirs.get(&canonical(dir)).is_some_and(|hash| *hash == content_hash(dir))
+    }
+
+    pub fn trust(&mut self, dir: &Path) -> Result<()> {
+        self.dirs.insert(canonical(dir), content_hash(dir));
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents: Vec<String> = self.dirs.iter()
+            .map(|(d, hash)| format!("{} {}", hash, d.to_string_lossy()))
+            .collect();
+        fs::write(&self.path, contents.join("\n") + "\n")?;
+        Ok(())
+    }
+}
+
+/// SHA-256 over the names and contents of the files in `dir`, in name order.
+/// Subdirectories aren't loaded as rules, so they're left out.
+fn content_hash(dir: &Path) -> String {
+    let mut files = BTreeMap::new();
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            if entry.path().is_file() {
+                files.insert(entry.file_name(), entry.path());
+            }
+        }
+    }
+
+    let mut hasher = Sha256::new();
+    for (name, path) in files {
+        let contents = fs::read(&path).unwrap_or_default();
+        hasher.update(name.as_encoded_bytes());
+        hasher.update([0]);
+        hasher.update((contents.len() as u64).to_le_bytes());
+        hasher.update(&contents);
+    }
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn canonical(dir: &Path) -> PathBuf {
+    dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_rule_dirs_order() {
+        let root = tempdir().unwrap();
+        let system = root.path().join("system");
+        let user = root.path().join("user");
+        let extra = root.path().join("extra");
+        let project = root.path().join("repo");
+        let nested = project.join("src").join("deep");
+        for dir in [&system, &user, &extra, &nested, &project.join(PROJECT_RULES_DIR)] {
+            fs::create_dir_all(dir).unwrap();
+        }
+
+        let rules_path = env::join_paths([&extra, &root.path().join("missing")]).unwrap();
+        let dirs = rule_dirs_from(&system, &user, Some(rules_path), &nested);
+
+        let paths: Vec<&PathBuf> = dirs.iter().map(|d| &d.path).collect();
+        assert_eq!(paths, vec![&system, &user, &extra, &project.join(PROJECT_RULES_DIR)]);
+        assert!(!dirs[2].project_local);
+        assert!(dirs[3].project_local);
+    }
+
+    #[test]
+    fn test_rule_files_shadowing() {
+        let root = tempdir().unwrap();
+        let user = root.path().join("user");
+        let project = root.path().join("project");
+        fs::create_dir_all(&user).unwrap();
+        fs::create_dir_all(&project).unwrap();
+        fs::write(user.join("a.rhai"), "").unwrap();
+        fs::write(user.join("b.rhai"), "").unwrap();
+        fs::write(user.join("notes.txt"), "").unwrap();
+        fs::write(project.join("b.rhai"), "").unwrap();
+
+        let files = rule_files(&[user.clone(), project.clone()], "rhai");
+        assert_eq!(files, vec![user.join("a.rhai"), project.join("b.rhai")]);
+    }
+
+    #[test]
+    fn test_trust_store() {
+        let root = tempdir().unwrap();
+        let store_path = root.path().join("ffs").join("trusted_dirs");
+        let project = root.path().join("repo");
+        fs::create_dir_all(&project).unwrap();
+
+        let mut store = TrustStore::load(&store_path);
+        assert!(!store.is_trusted(&project));
+        store.trust(&project).unwrap();
+
+        let store = TrustStore::load(&store_path);
+        assert!(store.is_trusted(&project));
+    }
+
+    #[test]
+    fn test_trust_store_asks_again_after_change() {
+        let root = tempdir().unwrap();
+        let store_path = root.path().join("trusted_dirs");
+        let project = root.path().join("repo");
+        fs::create_dir_all(&project).unwrap();
+        fs::write(project.join("a.rhai"), "fn matches() { false }").unwrap();
+
+        let mut store = TrustStore::load(&store_path);
+        store.trust(&project).unwrap();
+        assert!(TrustStore::load(&store_path).is_trusted(&project));
+
+        fs::write(project.join("a.rhai"), "fn matches() { true }").unwrap();
+        assert!(!TrustStore::load(&store_path).is_trusted(&project));
+
+        store.trust(&project).unwrap();
+        fs::write(project.join("b.toml"), "").unwrap();
+        assert!(!TrustStore::load(&store_path).is_trusted(&project));
+
+        // A path-only entry from an older version
+        fs::write(&store_path, format!("{}\n", project.canonicalize().unwrap().display())).unwrap();
+        assert!(!TrustStore::load(&store_path).is_trusted(&project));
+    }
+}
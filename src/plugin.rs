@@ -0,0 +1,265 @@
+//! Rules compiled to WebAssembly and loaded from `*.wasm` files in the rule
+//! directories.
+//!
+//! The module gets no imports, so it can't touch the filesystem, network or
+//! environment; each call runs in a fresh instance with a fuel and memory
+//! budget. ABI version 1:
+//!
+//! - `memory`: the exported linear memory.
+//! - `ffs_alloc(len: i32) -> i32`: returns a buffer of `len` bytes for the host
+//!   to write the input into.
+//! - `ffs_matches(ptr: i32, len: i32) -> i32`: non-zero if the rule applies.
+//! - `ffs_get_corrections(ptr: i32, len: i32) -> i64`: returns the output
+//!   buffer packed as `(ptr << 32) | len`.
+//! - `ffs_abi_version() -> i32` (optional): must return 1 if present.
+//!
+//! The input is the JSON form of [`Command`] (`script`, `stdout`, `stderr`,
+//! `raw_stdout`, `raw_stderr`, `exit_code`); the output is a JSON array of
+//! objects with `command` and optional `side_effect` and `priority`.
+
+use crate::types::{Command, Correction};
+use crate::rules::Rule;
+use crate::paths::rule_files;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use wasmi::{Config, Engine, Instance, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+pub const ABI_VERSION: i32 = 1;
+
+const FUEL_PER_CALL: u64 = 50_000_000;
+const MAX_MEMORY: usize = 64 * 1024 * 1024;
+
+#[derive(Serialize)]
+struct WireCommand<'a> {
+    script: &'a str,
+    stdout: &'a str,
+    stderr: &'a str,
+    raw_stdout: &'a str,
+    raw_stderr: &'a str,
+    exit_code: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct WireCorrection {
+    command: String,
+    #[serde(default)]
+    side_effect: bool,
+    priority: Option<usize>,
+}
+
+pub struct WasmRule {
+    engine: Engine,
+    module: Module,
+    name: String,
+    fuel: u64,
+}
+
+impl std::fmt::Debug for WasmRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WasmRule")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl WasmRule {
+    pub fn new(name: String, wasm: &[u8]) -> Result<Self> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, wasm).map_err(|e| anyhow!("{}", e))?;
+
+        if module.imports().next().is_some() {
+            return Err(anyhow!("plugin modules must not have imports"));
+        }
+
+        let rule = Self { engine, module, name, fuel: FUEL_PER_CALL };
+        let (mut store, instance) = rule.instantiate()?;
+        if let Ok(version) = instance.get_typed_func::<(), i32>(&store, "ffs_abi_version") {
+            let version = version.call(&mut store, ()).map_err(|e| anyhow!("{}", e))?;
+            if version != ABI_VERSION {
+                return Err(anyhow!("unsupported plugin ABI version {}", version));
+            }
+        }
+        Ok(rule)
+    }
+
+    /// Overrides the per-call fuel budget.
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = fuel;
+        self
+    }
+
+    fn instantiate(&self) -> Result<(Store<StoreLimits>, Instance)> {
+        let limits = StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build();
+        let mut store = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(self.fuel).map_err(|e| anyhow!("{}", e))?;
+
+        let linker = Linker::new(&self.engine);
+        let instance = linker.instantiate_and_start(&mut store, &self.module).map_err(|e| anyhow!("{}", e))?;
+        Ok((store, instance))
+    }
+
+    /// Instantiates the module, writes `command` into its memory and calls
+    /// `export` with the buffer.
+    fn call<R: wasmi::WasmResults>(&self, export: &str, command: &Command) -> Result<(Store<StoreLimits>, Instance, R)> {
+        let (mut store, instance) = self.instantiate()?;
+        let memory = instance.get_memory(&store, "memory").ok_or_else(|| anyhow!("no exported memory"))?;
+
+        let input = serde_json::to_vec(&WireCommand {
+            script: &command.script,
+            stdout: &command.stdout,
+            stderr: &command.stderr,
+            raw_stdout: &command.raw_stdout,
+            raw_stderr: &command.raw_stderr,
+            exit_code: command.exit_code,
+        })?;
+        let len = i32::try_from(input.len())?;
+
+        let alloc = instance.get_typed_func::<i32, i32>(&store, "ffs_alloc").map_err(|e| anyhow!("{}", e))?;
+        let ptr = alloc.call(&mut store, len).map_err(|e| anyhow!("{}", e))?;
+        memory.write(&mut store, ptr as u32 as usize, &input).map_err(|e| anyhow!("{}", e))?;
+
+        let func = instance.get_typed_func::<(i32, i32), R>(&store, export).map_err(|e| anyhow!("{}", e))?;
+        let result = func.call(&mut store, (ptr, len)).map_err(|e| anyhow!("{}", e))?;
+        Ok((store, instance, result))
+    }
+
+    fn try_generate_corrections(&self, command: &Command) -> Result<Vec<Correction>> {
+        let (store, instance, packed) = self.call::<i64>("ffs_get_corrections", command)?;
+        let memory = instance.get_memory(&store, "memory").ok_or_else(|| anyhow!("no exported memory"))?;
+
+        let ptr = (packed as u64 >> 32) as usize;
+        let len = (packed as u64 & 0xffff_ffff) as usize;
+        // Checked before allocating, so a bogus length can't make the host
+        // allocate up to 4GiB
+        if ptr.checked_add(len).is_none_or(|end| end > memory.data_size(&store)) {
+            return Err(anyhow!("output buffer {}+{} is outside the plugin's memory", ptr, len));
+        }
+        let mut output = vec![0u8; len];
+        memory.read(&store, ptr, &mut output).map_err(|e| anyhow!("{}", e))?;
+
+        let corrections: Vec<WireCorrection> = serde_json::from_slice(&output)?;
+        Ok(corrections.into_iter()
+            .map(|c| Correction::new(c.command, c.side_effect, c.priority.unwrap_or(100)))
+            .collect())
+    }
+}
+
+impl Rule for WasmRule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn matches(&self, command: &Command) -> bool {
+        self.call::<i32>("ffs_matches", command).is_ok_and(|(_, _, matched)| matched != 0)
+    }
+
+    fn generate_corrections(&self, command: &Command) -> Vec<Correction> {
+        self.try_generate_corrections(command).unwrap_or_default()
+    }
+}
+
+pub fn load_wasm_rule(path: &Path) -> Result<WasmRule> {
+    let wasm = fs::read(path)?;
+    let name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    WasmRule::new(name, &wasm)
+}
+
+/// Loads `.wasm` rules from `dirs`, later directories shadowing earlier ones.
+pub fn load_wasm_rules(dirs: &[PathBuf]) -> Vec<WasmRule> {
+    rule_files(dirs, "wasm")
+        .iter()
+        .filter_map(|path| match load_wasm_rule(path) {
+            Ok(rule) => Some(rule),
+            Err(e) => {
+                eprintln!("Failed to load plugin {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Matches when the script starts with "gti" (input is `{"script":"...`, so
+    // the script begins at offset 11) and always suggests `git status`.
+    const PLUGIN: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (global $next (mut i32) (i32.const 2048))
+          (data (i32.const 1024) "[{\"command\":\"git status\",\"priority\":90}]")
+          (func (export "ffs_abi_version") (result i32) i32.const 1)
+          (func (export "ffs_alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            global.get $next
+            local.set $ptr
+            global.get $next
+            local.get $len
+            i32.add
+            global.set $next
+            local.get $ptr)
+          (func (export "ffs_matches") (param $ptr i32) (param $len i32) (result i32)
+            (i32.and
+              (i32.eq (i32.load8_u offset=11 (local.get $ptr)) (i32.const 103))
+              (i32.eq (i32.load8_u offset=12 (local.get $ptr)) (i32.const 116))))
+          (func (export "ffs_get_corrections") (param i32 i32) (result i64)
+            (i64.or (i64.shl (i64.const 1024) (i64.const 32)) (i64.const 40))))
+    "#;
+
+    #[test]
+    fn test_wasm_rule() {
+        let rule = WasmRule::new("gti".to_string(), PLUGIN.as_bytes()).unwrap();
+
+        let command = Command::new("gti status".to_string(), "".to_string(), "".to_string());
+        assert!(rule.matches(&command));
+        let corrections = rule.generate_corrections(&command);
+        assert_eq!(corrections.len(), 1);
+        assert_eq!(corrections[0].command, "git status");
+        assert_eq!(corrections[0].priority, 90);
+
+        let command = Command::new("ls".to_string(), "".to_string(), "".to_string());
+        assert!(!rule.matches(&command));
+    }
+
+    #[test]
+    fn test_wasm_rule_rejects_imports() {
+        let wasm = r#"(module (import "env" "open" (func)))"#;
+        assert!(WasmRule::new("bad".to_string(), wasm.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_wasm_rule_runs_out_of_fuel() {
+        let wasm = r#"
+            (module
+              (memory (export "memory") 1)
+              (func (export "ffs_alloc") (param i32) (result i32) i32.const 0)
+              (func (export "ffs_matches") (param i32 i32) (result i32)
+                (loop $spin (br $spin))
+                i32.const 1))
+        "#;
+        let rule = WasmRule::new("spin".to_string(), wasm.as_bytes()).unwrap().with_fuel(10_000);
+        let command = Command::new("ls".to_string(), "".to_string(), "".to_string());
+        assert!(!rule.matches(&command));
+    }
+
+    #[test]
+    fn test_wasm_rule_output_outside_memory() {
+        let wasm = r#"
+            (module
+              (memory (export "memory") 1)
+              (func (export "ffs_alloc") (param i32) (result i32) i32.const 0)
+              (func (export "ffs_get_corrections") (param i32 i32) (result i64)
+                (i64.or (i64.shl (i64.const 1024) (i64.const 32)) (i64.const 0xffffffff))))
+        "#;
+        let rule = WasmRule::new("huge".to_string(), wasm.as_bytes()).unwrap();
+        let command = Command::new("ls".to_string(), "".to_string(), "".to_string());
+        let err = rule.try_generate_corrections(&command).unwrap_err();
+        assert!(err.to_string().contains("outside the plugin's memory"));
+    }
+}
@@ -1,5 +1,6 @@
 use crate::types::{Command, Correction};
 use crate::rules::Rule;
+use crate::messages::{self, Message};
 use shell_words::split;
 
 #[derive(Debug)]
@@ -15,7 +16,7 @@ impl Rule for CdMkdir {
         let stderr = command.stderr.to_lowercase();
 
         script.starts_with("cd ") &&
-        (stderr.contains("no such file or directory") ||
+        (messages::contains(&stderr, Message::NoSuchFile) ||
          stderr.contains("does not exist") ||
          stderr.contains("can't cd to"))
     }
@@ -0,0 +1,158 @@
+use crate::types::{Command, Correction};
+use crate::rules::Rule;
+use regex::Regex;
+use once_cell::sync::Lazy;
+use shell_words::split;
+use strsim::normalized_damerau_levenshtein;
+
+/// A heading followed by indented suggestions, one per line, as git, cobra
+/// CLIs (kubectl, gh) and npm print them.
+static LIST_RE: Lazy<Regex> = Lazy::new(|| Regex::new(
+    r"(?mi)(?:did you mean (?:this|one of these)\?|the most similar commands? (?:is|are))[ \t]*\n((?:[ \t]+\S.*(?:\n|$))+)"
+).unwrap());
+
+/// A suggestion on the same line: cargo, pip, terraform, clap's tips.
+static INLINE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(
+    r#"(?i)(?:did you mean|maybe you meant|similar (?:sub)?command exists:|similar argument exists:|similar name exists:)[ \t]+["'`]?(-{0,2}\w[\w.:/-]*)["'`]?"#
+).unwrap());
+
+/// The token the tool didn't recognize.
+static UNKNOWN_RE: Lazy<Regex> = Lazy::new(|| Regex::new(
+    r#"(?i)(?:unknown|unrecognized|unexpected|invalid|no such)[ \t]+(?:shorthand[ \t]+)?(?:sub)?(?:command|flag|option|argument|choice)[:= \t]+["'`]?([^\s"'`,]+)"#
+).unwrap());
+static NOT_A_COMMAND_RE: Lazy<Regex> = Lazy::new(|| Regex::new(
+    r#"["'`]([^\s"'`]+)["'`] is not an? [\w-]+ command"#
+).unwrap());
+
+/// Minimum similarity for guessing which argument a suggestion replaces when
+/// the tool doesn't say which one it didn't recognize.
+const MIN_SIMILARITY: f64 = 0.5;
+
+/// Applies the "did you mean" hints most CLIs print for a mistyped subcommand
+/// or flag, by replacing the unrecognized argument with each suggestion.
+#[derive(Debug)]
+pub struct DidYouMean;
+
+impl Rule for DidYouMean {
+    fn name(&self) -> &str {
+        "did_you_mean"
+    }
+
+    fn matches(&self, command: &Command) -> bool {
+        [&command.stderr, &command.stdout].iter()
+            .any(|output| LIST_RE.is_match(output) || INLINE_RE.is_match(output))
+    }
+
+    fn generate_corrections(&self, command: &Command) -> Vec<Correction> {
+        let output = format!("{}\n{}", command.stderr, command.stdout);
+        let Ok(parts) = split(&command.script) else {
+            return vec![];
+        };
+        let Some(program) = parts.iter().find(|p| *p != "sudo") else {
+            return vec![];
+        };
+
+        let suggestions = extract_suggestions(&output, program);
+        let unknown = extract_unknown(&output);
+
+        let mut corrections: Vec<Correction> = Vec::new();
+        for suggestion in suggestions {
+            let position = match &unknown {
+                Some(token) => find_token(&parts, token),
+                None => closest_argument(&parts, &suggestion),
+            };
+            let Some(position) = position else { continue };
+
+            // Keep the value of `--flag=value`
+            let replacement = match parts[position].split_once('=') {
+                Some((_, value)) if suggestion.starts_with('-') => format!("{}={}", suggestion, value),
+                _ => suggestion,
+            };
+            if replacement == parts[position] {
+                continue;
+            }
+
+            // Below the rules written for a specific tool
+            let new_cmd = replace_argument(&command.script, &parts, position, &replacement);
+            if corrections.iter().all(|c| c.command != new_cmd) {
+                corrections.push(Correction::new(new_cmd, false, 90));
+            }
+        }
+        corrections
+    }
+}
+
+/// Suggested words in the order the tool listed them.
+pub(crate) fn extract_suggestions(output: &str, program: &str) -> Vec<String> {
+    let mut suggestions: Vec<String> = Vec::new();
+
+    for caps in LIST_RE.captures_iter(output) {
+        for line in caps[1].lines() {
+            // npm prints `npm install # Install a package`
+            let line = line.split(" #").next().unwrap_or_default();
+            if let Some(word) = line.split_whitespace().map(trim_token).find(|w| *w != program && !w.is_empty()) {
+                suggestions.push(word.to_string());
+            }
+        }
+    }
+
+    for caps in INLINE_RE.captures_iter(output) {
+        let word = trim_token(&caps[1]);
+        // The heading of a list, handled above
+        if !["this", "one"].contains(&word.to_lowercase().as_str()) {
+            suggestions.push(word.to_string());
+        }
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    suggestions.retain(|s| seen.insert(s.clone()));
+    suggestions
+}
+
+pub(crate) fn extract_unknown(output: &str) -> Option<String> {
+    UNKNOWN_RE.captures(output)
+        .or_else(|| NOT_A_COMMAND_RE.captures(output))
+        .map(|caps| trim_token(&caps[1]).to_string())
+}
+
+fn trim_token(token: &str) -> &str {
+    token.trim_matches(|c: char| matches!(c, '"' | '\'' | '`' | '?' | '.' | ',' | ':' | ';'))
+}
+
+/// `script` with argument `position` replaced, keeping the rest as typed when
+/// the argument appears unquoted.
+pub(crate) fn replace_argument(script: &str, parts: &[String], position: usize, replacement: &str) -> String {
+    // Skip past the program, which can't be a suggestion's target
+    let start = script.split_whitespace().next().map_or(0, |w| w.as_ptr() as usize - script.as_ptr() as usize + w.len());
+    let found = script[start..].split_whitespace()
+        .find(|w| *w == parts[position])
+        .map(|w| w.as_ptr() as usize - script.as_ptr() as usize);
+
+    match found {
+        Some(offset) => format!("{}{}{}", &script[..offset], replacement, &script[offset + parts[position].len()..]),
+        None => {
+            let mut new_parts = parts.to_vec();
+            new_parts[position] = replacement.to_string();
+            shell_words::join(new_parts)
+        }
+    }
+}
+
+/// Index of the argument the tool reported as unknown, skipping the program.
+fn find_token(parts: &[String], token: &str) -> Option<usize> {
+    parts.iter().enumerate().skip(1)
+        .find(|(_, part)| *part == token || part.split_once('=').is_some_and(|(flag, _)| flag == token))
+        .map(|(i, _)| i)
+}
+
+/// Index of the argument most similar to `suggestion`, skipping the program.
+fn closest_argument(parts: &[String], suggestion: &str) -> Option<usize> {
+    parts.iter().enumerate().skip(1)
+        .map(|(i, part)| {
+            let part = part.split_once('=').map_or(part.as_str(), |(flag, _)| flag);
+            (i, normalized_damerau_levenshtein(part, suggestion))
+        })
+        .filter(|(_, similarity)| *similarity >= MIN_SIMILARITY && *similarity < 1.0)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| i)
+}
@@ -0,0 +1,52 @@
+#[cfg(test)]
+mod tests {
+    use crate::rules::did_you_mean::DidYouMean;
+    use crate::types::Command;
+    use crate::rules::Rule;
+
+    fn corrections(script: &str, stderr: &str) -> Vec<String> {
+        let command = Command::new(script.to_string(), "".to_string(), stderr.to_string());
+        DidYouMean.generate_corrections(&command).into_iter().map(|c| c.command).collect()
+    }
+
+    #[test]
+    fn test_cobra_list() {
+        let stderr = "error: unknown command \"gett\" for \"kubectl\"\n\nDid you mean this?\n\tget\n\nRun 'kubectl --help' for usage.\n";
+        assert_eq!(corrections("kubectl gett pods", stderr), vec!["kubectl get pods"]);
+    }
+
+    #[test]
+    fn test_npm_list_with_descriptions() {
+        let stderr = "Unknown command: \"isntall\"\n\nDid you mean one of these?\n    npm install # Install a package\n    npm uninstall # Remove a package\n";
+        assert_eq!(corrections("npm isntall left-pad", stderr), vec!["npm install left-pad", "npm uninstall left-pad"]);
+    }
+
+    #[test]
+    fn test_git_most_similar() {
+        let stderr = "git: 'stauts' is not a git command. See 'git --help'.\n\nThe most similar command is\n\tstatus\n";
+        assert_eq!(corrections("git stauts -s", stderr), vec!["git status -s"]);
+    }
+
+    #[test]
+    fn test_clap_tip_keeps_flag_value() {
+        let stderr = "error: unexpected argument '--colour' found\n\n  tip: a similar argument exists: '--color'\n";
+        assert_eq!(corrections("rg --colour=always foo", stderr), vec!["rg --color=always foo"]);
+    }
+
+    #[test]
+    fn test_inline_quoted() {
+        let stderr = "Terraform has no command named \"plna\". Did you mean \"plan\"?\n";
+        assert_eq!(corrections("terraform plna -out plan.tfplan", stderr), vec!["terraform plan -out plan.tfplan"]);
+
+        let stderr = "ERROR: unknown command \"instal\" - maybe you meant \"install\"\n";
+        assert_eq!(corrections("sudo pip instal requests", stderr), vec!["sudo pip install requests"]);
+    }
+
+    #[test]
+    fn test_no_suggestion() {
+        let command = Command::new("docker biuld .".to_string(), "".to_string(),
+            "docker: 'biuld' is not a docker command.\nSee 'docker --help'\n".to_string());
+        assert!(!DidYouMean.matches(&command));
+        assert!(corrections("foo", "foo: command not found").is_empty());
+    }
+}
@@ -1,14 +1,36 @@
 use crate::types::{Command, Correction};
 use crate::rules::Rule;
-use std::env;
-use std::fs;
-use std::path::Path;
-use strsim::levenshtein;
+use crate::messages::{self, Message};
+use crate::executables::{self, ExecutableIndex};
+use crate::typo::{DefaultScorer, TypoScorer};
 use std::collections::HashSet;
 use shell_words::split;
 
 #[derive(Debug)]
-pub struct UnknownCommand;
+pub struct UnknownCommand {
+    /// Aliases and functions defined in the user's shell, which are valid
+    /// commands even though they aren't on `$PATH`.
+    shell_commands: Vec<String>,
+    scorer: Box<dyn TypoScorer>,
+}
+
+impl Default for UnknownCommand {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl UnknownCommand {
+    pub fn new(shell_commands: Vec<String>) -> Self {
+        Self { shell_commands, scorer: Box::new(DefaultScorer::default()) }
+    }
+
+    /// Ranks candidates with `scorer` instead of the default model.
+    pub fn with_scorer(mut self, scorer: impl TypoScorer + 'static) -> Self {
+        self.scorer = Box::new(scorer);
+        self
+    }
+}
 
 impl Rule for UnknownCommand {
     fn name(&self) -> &str {
@@ -20,7 +42,7 @@ impl Rule for UnknownCommand {
         if script.is_empty() { return false; }
 
         let lower_stderr = command.stderr.to_lowercase();
-        lower_stderr.contains("command not found") ||
+        messages::contains(&lower_stderr, Message::CommandNotFound) ||
         lower_stderr.contains("unknown command") ||
         lower_stderr.contains("is not recognized as an internal or external command")
     }
@@ -37,7 +59,7 @@ impl Rule for UnknownCommand {
         let typed_command = &parts[0];
 
         // Find similar commands
-        let candidates = find_similar_commands(typed_command);
+        let candidates = find_similar_commands(typed_command, &self.shell_commands, executables::shared(), self.scorer.as_ref());
 
         let mut corrections = Vec::new();
         for candidate in candidates {
@@ -57,70 +79,23 @@ impl Rule for UnknownCommand {
     }
 }
 
-fn find_similar_commands(target: &str) -> Vec<String> {
-    let path_var = env::var("PATH").unwrap_or_default();
+fn find_similar_commands(
+    target: &str,
+    shell_commands: &[String],
+    executables: &ExecutableIndex,
+    scorer: &dyn TypoScorer,
+) -> Vec<String> {
     let mut candidates = HashSet::new(); // Use Set to avoid duplicates
+    candidates.extend(shell_commands.iter().cloned());
+    candidates.extend(executables.find_similar(target, scorer.search_radius(target)).into_iter().map(|(name, _)| name));
 
-    for path_str in env::split_paths(&path_var) {
-        if let Ok(entries) = fs::read_dir(path_str) {
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    if let Ok(file_name) = entry.file_name().into_string() {
-                         if is_executable(&entry.path()) {
-                             let dist = levenshtein(target, &file_name);
-                             let max_dist = 3; // Tolerance
-
-                             if dist > 0 && dist <= max_dist {
-                                 // Filter by ratio to avoid short command false positives
-                                 let ratio = dist as f64 / target.len().max(file_name.len()) as f64;
-                                 if ratio < 0.5 {
-                                     candidates.insert(file_name);
-                                 }
-                             }
-                         }
-                    }
-                }
-            }
-        }
-    }
-
-    // Sort by distance
-    let mut sorted_candidates: Vec<String> = candidates.into_iter().collect();
-    sorted_candidates.sort_by(|a, b| {
-        levenshtein(target, a).cmp(&levenshtein(target, b))
-    });
+    // Best score first
+    let mut scored: Vec<(f64, String)> = candidates.into_iter()
+        .filter_map(|name| scorer.score(target, &name).map(|score| (score, name)))
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
 
-    sorted_candidates.truncate(3); // Top 3
-    sorted_candidates
-}
-
-fn is_executable(path: &Path) -> bool {
-    // In Unix, check execute permission. In Windows, check extension.
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        if let Ok(metadata) = path.metadata() {
-            return metadata.permissions().mode() & 0o111 != 0;
-        }
-    }
-    #[cfg(windows)]
-    {
-        if let Some(ext) = path.extension() {
-             let ext_str = ext.to_string_lossy().to_lowercase();
-             return ext_str == "exe" || ext_str == "cmd" || ext_str == "bat";
-        }
-    }
-
-    #[cfg(not(any(unix, windows)))]
-    {
-        // Fallback for other OS or if we can't determine
-        if let Ok(metadata) = path.metadata() {
-            // Rough check: is it a file?
-             return metadata.is_file();
-        }
-    }
-
-    false
+    scored.into_iter().take(3).map(|(_, name)| name).collect() // Top 3
 }
 
 #[cfg(test)]
@@ -128,13 +103,11 @@ mod tests {
     use super::*;
     use tempfile::tempdir;
     use std::fs::File;
-    use serial_test::serial;
 
     #[cfg(unix)]
     use std::os::unix::fs::PermissionsExt;
 
     #[test]
-    #[serial]
     fn test_find_similar_commands() {
         let dir = tempdir().unwrap();
 
@@ -169,19 +142,10 @@ mod tests {
              File::create(dir.path().join("car")).unwrap();
         }
 
-        let original_path = env::var("PATH").unwrap_or_default();
-        let new_path = if cfg!(windows) {
-             // On Windows, paths are separated by ;
-             format!("{};{}", dir.path().to_string_lossy(), original_path)
-        } else {
-             format!("{}:{}", dir.path().to_string_lossy(), original_path)
-        };
-
-        env::set_var("PATH", new_path);
+        let mut executables = ExecutableIndex::default();
+        executables.refresh(&[dir.path().to_path_buf()]);
 
-        let similar = find_similar_commands("carg");
-
-        env::set_var("PATH", original_path); // Restore
+        let similar = find_similar_commands("carg", &[], &executables, &DefaultScorer::default());
 
         // On Windows the extension might or might not be returned depending on file iteration
         // But our logic returns filename as is.
@@ -195,9 +159,18 @@ mod tests {
         assert!(!similar.contains(&"car".to_string()));
     }
 
+    #[test]
+    fn test_unknown_command_shell_commands() {
+        let rule = UnknownCommand::new(vec!["deploy".to_string()]);
+        let cmd = Command::new("deplyo --prod".to_string(), "".to_string(), "bash: deplyo: command not found".to_string());
+
+        let corrections = rule.generate_corrections(&cmd);
+        assert!(corrections.iter().any(|c| c.command == "deploy --prod"));
+    }
+
     #[test]
     fn test_unknown_command_matches() {
-        let rule = UnknownCommand;
+        let rule = UnknownCommand::default();
         let cmd = Command::new("foo".to_string(), "".to_string(), "bash: foo: command not found".to_string());
         assert!(rule.matches(&cmd));
 
@@ -0,0 +1,93 @@
+use crate::types::{Command, Correction};
+use crate::rules::Rule;
+use crate::utils::is_ffs_call;
+use std::collections::HashMap;
+use strsim::normalized_levenshtein;
+
+/// Minimum similarity between the failed command and a history entry.
+const MIN_SIMILARITY: f64 = 0.6;
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Suggests commands from the shell history that are close to the failed one,
+/// so long commands the user types often get fixed without a specific rule.
+#[derive(Debug, Default)]
+pub struct History {
+    /// Entries that didn't fail, oldest first, with how often each was run
+    /// and the position it was last run at.
+    candidates: Vec<(String, usize, usize)>,
+    len: usize,
+}
+
+impl History {
+    /// Takes history entries oldest first, as from `Shell::get_history`.
+    /// Entries that were followed by a call to ffs, or immediately retyped
+    /// with a small change, are taken to have failed and skipped.
+    pub fn new(entries: Vec<String>, alias: Option<&str>) -> Self {
+        let entries: Vec<String> = entries.into_iter()
+            .map(|e| e.trim().to_string())
+            .filter(|e| !e.is_empty())
+            .collect();
+
+        let mut stats: HashMap<&str, (usize, usize)> = HashMap::new();
+        for (i, entry) in entries.iter().enumerate() {
+            if is_ffs_call(entry, alias) {
+                continue;
+            }
+            let failed = entries.get(i + 1).is_some_and(|next| {
+                is_ffs_call(next, alias) || (next != entry && normalized_levenshtein(entry, next) >= 0.8)
+            });
+            if !failed {
+                let stat = stats.entry(entry).or_insert((0, i));
+                stat.0 += 1;
+                stat.1 = i;
+            }
+        }
+
+        let mut candidates: Vec<(String, usize, usize)> = stats.into_iter()
+            .map(|(entry, (count, last))| (entry.to_string(), count, last))
+            .collect();
+        candidates.sort_by_key(|(_, _, last)| *last);
+        Self { candidates, len: entries.len() }
+    }
+
+    /// The closest entries to `script`, best first. More frequent and more
+    /// recent entries win between equally close ones.
+    fn suggestions(&self, script: &str) -> Vec<String> {
+        let script = script.trim();
+        let mut scored: Vec<(f64, &str)> = self.candidates.iter()
+            .filter(|(entry, _, _)| entry != script)
+            .filter_map(|(entry, count, last)| {
+                let similarity = normalized_levenshtein(script, entry);
+                if similarity < MIN_SIMILARITY {
+                    return None;
+                }
+                let frequency = (*count as f64).ln_1p() * 0.05;
+                let recency = (*last + 1) as f64 / self.len.max(1) as f64 * 0.05;
+                Some((similarity + frequency + recency, entry.as_str()))
+            })
+            .collect();
+
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.into_iter().take(MAX_SUGGESTIONS).map(|(_, entry)| entry.to_string()).collect()
+    }
+}
+
+impl Rule for History {
+    fn name(&self) -> &str {
+        "history"
+    }
+
+    fn matches(&self, command: &Command) -> bool {
+        command.exit_code != Some(0) && !self.suggestions(&command.script).is_empty()
+    }
+
+    fn generate_corrections(&self, command: &Command) -> Vec<Correction> {
+        // Below specific rules, and below unknown_command which knows the
+        // command doesn't exist
+        self.suggestions(&command.script)
+            .into_iter()
+            .enumerate()
+            .map(|(i, entry)| Correction::new(entry, false, 40 - i))
+            .collect()
+    }
+}
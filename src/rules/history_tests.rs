@@ -0,0 +1,62 @@
+#[cfg(test)]
+mod tests {
+    use crate::rules::history::History;
+    use crate::types::Command;
+    use crate::rules::Rule;
+
+    fn history(entries: &[&str]) -> History {
+        History::new(entries.iter().map(|e| e.to_string()).collect(), Some("fuck"))
+    }
+
+    fn failed(script: &str) -> Command {
+        Command::new(script.to_string(), "".to_string(), "error".to_string()).with_exit_code(Some(1))
+    }
+
+    #[test]
+    fn test_history_suggests_close_entries() {
+        let rule = history(&[
+            "docker compose -f deploy/prod.yml up -d",
+            "ls",
+            "docker compose -f deploy/prod.yml logs -f",
+        ]);
+
+        let command = failed("docker compose -f deploy/prd.yml up -d");
+        assert!(rule.matches(&command));
+        let corrections = rule.generate_corrections(&command);
+        assert_eq!(corrections[0].command, "docker compose -f deploy/prod.yml up -d");
+        assert!(corrections.iter().all(|c| c.command != "ls"));
+    }
+
+    #[test]
+    fn test_history_skips_failed_entries() {
+        let rule = history(&[
+            // Followed by a call to ffs
+            "kubectl get pods -n kube-sytem",
+            "fuck",
+            // Retyped straight away
+            "kubectl get pods -n kube-systen",
+            "kubectl get pods -n kube-system",
+        ]);
+
+        let corrections = rule.generate_corrections(&failed("kubectl get pods -n kube-sistem"));
+        let commands: Vec<_> = corrections.iter().map(|c| c.command.as_str()).collect();
+        assert_eq!(commands, vec!["kubectl get pods -n kube-system"]);
+    }
+
+    #[test]
+    fn test_history_prefers_frequent_entries() {
+        // Both are one edit away; test-a was run more often, test-b more recently
+        let rule = history(&["make test-a", "ls", "make test-a", "pwd", "make test-b", "cd"]);
+        let corrections = rule.generate_corrections(&failed("make test-c"));
+        assert_eq!(corrections[0].command, "make test-a");
+        assert_eq!(corrections[1].command, "make test-b");
+    }
+
+    #[test]
+    fn test_history_ignores_successful_commands() {
+        let rule = history(&["git status"]);
+        let command = Command::new("git statsu".to_string(), "".to_string(), "".to_string()).with_exit_code(Some(0));
+        assert!(!rule.matches(&command));
+        assert!(!rule.matches(&failed("cargo build")));
+    }
+}
@@ -1,5 +1,6 @@
 use crate::types::{Command, Correction};
 use crate::rules::Rule;
+use crate::messages::{self, Message};
 use shell_words::split;
 
 #[derive(Debug)]
@@ -22,7 +23,7 @@ impl Rule for MkdirP {
         // Exact match for "mkdir"
         if parts[0] != "mkdir" { return false; }
 
-        command.stderr.contains("No such file or directory")
+        messages::contains(&command.stderr, Message::NoSuchFile)
     }
 
     fn generate_corrections(&self, command: &Command) -> Vec<Correction> {
@@ -20,6 +20,16 @@ mod tests {
         assert_eq!(corrections[0].command, "mkdir -p foo/bar");
     }
 
+    #[test]
+    fn test_mkdir_p_localized() {
+        let command = Command::new(
+            "mkdir foo/bar".to_string(),
+            "".to_string(),
+            "mkdir: Verzeichnis »foo/bar« kann nicht angelegt werden: Datei oder Verzeichnis nicht gefunden".to_string(),
+        );
+        assert!(MkdirP.matches(&command));
+    }
+
     #[test]
     fn test_mkdir_p_no_match() {
         let rule = MkdirP;
@@ -1,6 +1,5 @@
 use crate::types::{Command, Correction};
 use std::fmt::Debug;
-use anyhow::Result;
 
 pub trait Rule: Send + Sync + Debug {
     fn matches(&self, command: &Command) -> bool;
@@ -16,6 +15,9 @@ pub mod mkdir;
 pub mod sudo;
 pub mod cd;
 pub mod python;
+pub mod history;
+pub mod did_you_mean;
+pub mod unknown_argument;
 
 #[cfg(test)]
 mod mkdir_tests;
@@ -27,3 +29,9 @@ mod cd_tests;
 mod git_tests;
 #[cfg(test)]
 mod python_tests;
+#[cfg(test)]
+mod history_tests;
+#[cfg(test)]
+mod did_you_mean_tests;
+#[cfg(test)]
+mod unknown_argument_tests;
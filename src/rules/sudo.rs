@@ -1,5 +1,6 @@
 use crate::types::{Command, Correction};
 use crate::rules::Rule;
+use crate::messages::{self, Message};
 
 #[derive(Debug)]
 pub struct Sudo;
@@ -13,9 +14,9 @@ impl Rule for Sudo {
         let stderr = command.stderr.to_lowercase();
         let stdout = command.stdout.to_lowercase();
 
-        (stderr.contains("permission denied") ||
+        (messages::contains(&stderr, Message::PermissionDenied) ||
          stderr.contains("eacces") ||
-         stdout.contains("permission denied") ||
+         messages::contains(&stdout, Message::PermissionDenied) ||
          stdout.contains("eacces") ||
          stderr.contains("requires root privileges") ||
          stderr.contains("must be run as root")) &&
@@ -0,0 +1,377 @@
+use crate::types::{Command, Correction};
+use crate::rules::Rule;
+use crate::rules::did_you_mean::{extract_suggestions, extract_unknown, replace_argument};
+use crate::typo::{DefaultScorer, TypoScorer};
+use crate::messages::english_locale;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use shell_words::split;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command as SysCommand, Stdio};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+/// How long `<program> --help` may take before it's killed.
+const HELP_TIMEOUT: Duration = Duration::from_millis(500);
+/// Subcommands passed along to `--help`, as in `kubectl get --help`.
+const MAX_SUBCOMMAND_DEPTH: usize = 2;
+const MAX_SUGGESTIONS: usize = 3;
+
+static FLAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:^|[\s,\[(|/])(--[A-Za-z0-9][\w-]*|-[A-Za-z0-9])\b").unwrap());
+/// Git's `--[no-]verify`.
+static NEGATABLE_FLAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"--\[no-\]([A-Za-z0-9][\w-]*)").unwrap());
+static COMMANDS_HEADING_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)commands?[^:]*:\s*$").unwrap());
+/// `  build, b    Compile the current package`, or npm's `  access, adduser,`
+static SUBCOMMAND_LINE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^\s+([a-z][\w-]*(?:,\s*[a-z][\w-]*)*),?(?:\s{2,}|\s*$)").unwrap()
+});
+
+/// Valid subcommands and flags of a program.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Vocabulary {
+    #[serde(default)]
+    pub subcommands: Vec<String>,
+    #[serde(default)]
+    pub flags: Vec<String>,
+}
+
+impl Vocabulary {
+    fn extend(&mut self, other: Vocabulary) {
+        for subcommand in other.subcommands {
+            if !self.subcommands.contains(&subcommand) {
+                self.subcommands.push(subcommand);
+            }
+        }
+        for flag in other.flags {
+            if !self.flags.contains(&flag) {
+                self.flags.push(flag);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedVocabulary {
+    /// Program and subcommands, e.g. `kubectl get`.
+    command: String,
+    /// The resolved binary, whose mtime and size stand in for its version.
+    binary: String,
+    mtime: u64,
+    size: u64,
+    #[serde(default)]
+    subcommands: Vec<String>,
+    #[serde(default)]
+    flags: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct HelpCache {
+    #[serde(default)]
+    entry: Vec<CachedVocabulary>,
+}
+
+/// Replaces a subcommand or flag the program reported as unknown, without
+/// suggesting anything, with the closest one from its `--help` output or
+/// shell completions. What a binary accepts is cached until it changes.
+#[derive(Debug)]
+pub struct UnknownArgument {
+    cache_path: PathBuf,
+    cache: Mutex<Option<HelpCache>>,
+    scorer: Box<dyn TypoScorer>,
+}
+
+impl Default for UnknownArgument {
+    fn default() -> Self {
+        Self::new(Self::default_cache_path())
+    }
+}
+
+impl UnknownArgument {
+    pub fn new(cache_path: PathBuf) -> Self {
+        Self { cache_path, cache: Mutex::new(None), scorer: Box::new(DefaultScorer::default()) }
+    }
+
+    pub fn default_cache_path() -> PathBuf {
+        let cache_dir = dirs::cache_dir().unwrap_or_else(|| PathBuf::from("."));
+        cache_dir.join("ffs").join("help.toml")
+    }
+
+    /// Ranks candidates with `scorer` instead of the default model.
+    pub fn with_scorer(mut self, scorer: impl TypoScorer + 'static) -> Self {
+        self.scorer = Box::new(scorer);
+        self
+    }
+
+    /// What `words`, a program and its subcommands, accepts, from the cache
+    /// if the binary didn't change since it was last asked.
+    fn vocabulary(&self, words: &[String]) -> Option<Vocabulary> {
+        let binary = if words[0].contains('/') {
+            PathBuf::from(&words[0])
+        } else {
+            which::which(&words[0]).ok()?
+        };
+        let metadata = fs::metadata(&binary).ok()?;
+        let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+        let (command, binary_key) = (words.join(" "), binary.to_string_lossy().to_string());
+
+        let mut guard = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        let cache = guard.get_or_insert_with(|| {
+            fs::read_to_string(&self.cache_path)
+                .ok()
+                .and_then(|contents| toml::from_str(&contents).ok())
+                .unwrap_or_default()
+        });
+        if let Some(cached) = cache.entry.iter().find(|e| {
+            e.command == command && e.binary == binary_key && e.mtime == mtime && e.size == metadata.len()
+        }) {
+            return Some(Vocabulary { subcommands: cached.subcommands.clone(), flags: cached.flags.clone() });
+        }
+
+        let program = binary.file_name()?.to_string_lossy().to_string();
+        let mut vocabulary = run_help(&binary, &words[1..]).map(|help| parse_help(&help)).unwrap_or_default();
+        vocabulary.extend(completion_vocabulary(&program, words.len() == 1));
+
+        // Cached even when empty, so a program without help isn't asked again
+        cache.entry.retain(|e| e.command != command);
+        cache.entry.push(CachedVocabulary {
+            command,
+            binary: binary_key,
+            mtime,
+            size: metadata.len(),
+            subcommands: vocabulary.subcommands.clone(),
+            flags: vocabulary.flags.clone(),
+        });
+        if let Some(parent) = self.cache_path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        if let Ok(contents) = toml::to_string(&*cache) {
+            let _ = fs::write(&self.cache_path, contents);
+        }
+        Some(vocabulary)
+    }
+}
+
+impl Rule for UnknownArgument {
+    fn name(&self) -> &str {
+        "unknown_argument"
+    }
+
+    fn matches(&self, command: &Command) -> bool {
+        // Without asking for help, which is left to generate_corrections
+        command.exit_code != Some(0)
+            && extract_unknown(&format!("{}\n{}", command.stderr, command.stdout)).is_some()
+    }
+
+    fn generate_corrections(&self, command: &Command) -> Vec<Correction> {
+        let output = format!("{}\n{}", command.stderr, command.stdout);
+        let Ok(parts) = split(&command.script) else {
+            return vec![];
+        };
+        let Some(start) = parts.iter().position(|p| p != "sudo") else {
+            return vec![];
+        };
+        let words = &parts[start..];
+
+        // Tools that suggest something themselves are left to did_you_mean
+        if !extract_suggestions(&output, &words[0]).is_empty() {
+            return vec![];
+        }
+        let Some(token) = extract_unknown(&output) else {
+            return vec![];
+        };
+        let Some(position) = words.iter().skip(1).position(|w| {
+            w == &token || w.split_once('=').is_some_and(|(flag, _)| flag == token)
+        }) else {
+            return vec![];
+        };
+        let position = position + 1;
+
+        // Only descend into words the level above lists as subcommands, so
+        // `--help` is never run with the user's positional arguments
+        let mut context = vec![words[0].clone()];
+        let Some(mut vocabulary) = self.vocabulary(&context) else {
+            return vec![];
+        };
+        for word in &words[1..position] {
+            if context.len() > MAX_SUBCOMMAND_DEPTH || !vocabulary.subcommands.contains(word) {
+                break;
+            }
+            context.push(word.clone());
+            match self.vocabulary(&context) {
+                Some(nested) => vocabulary = nested,
+                None => break,
+            }
+        }
+
+        let candidates = if token.starts_with('-') { &vocabulary.flags } else { &vocabulary.subcommands };
+        let mut scored: Vec<(f64, &String)> = candidates.iter()
+            .filter_map(|c| self.scorer.score(&token, c).map(|score| (score, c)))
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+
+        scored.into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, candidate)| {
+                let replacement = match words[position].split_once('=') {
+                    Some((_, value)) => format!("{}={}", candidate, value),
+                    None => candidate.clone(),
+                };
+                let new_cmd = replace_argument(&command.script, &parts, start + position, &replacement);
+                // Alongside unknown_command, below tool-specific rules
+                Correction::new(new_cmd, false, 60)
+            })
+            .collect()
+    }
+}
+
+/// Output of `binary <args> --help`, or `None` if it can't run or its output
+/// isn't complete within [`HELP_TIMEOUT`].
+fn run_help(binary: &Path, args: &[String]) -> Option<String> {
+    let mut child = SysCommand::new(binary)
+        .args(args)
+        .arg("--help")
+        // `git commit --help` opens the man page
+        .env("PAGER", "cat")
+        .env("MANPAGER", "cat")
+        .env("GIT_PAGER", "cat")
+        // The headings parsed below are in English
+        .env("LC_ALL", english_locale())
+        // GNU gettext prefers LANGUAGE over LC_ALL
+        .env_remove("LANGUAGE")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+
+    // Read both pipes while waiting so a chatty program can't fill one and block
+    let (tx, rx) = mpsc::channel();
+    read_pipe(0, child.stdout.take()?, tx.clone());
+    read_pipe(1, child.stderr.take()?, tx);
+
+    let deadline = Instant::now() + HELP_TIMEOUT;
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break,
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+        }
+    }
+
+    // A process the program left behind, such as a pager, can hold the pipes
+    // open after it exits
+    let mut outputs = Vec::new();
+    for _ in 0..2 {
+        outputs.push(rx.recv_timeout(deadline.saturating_duration_since(Instant::now())).ok()?);
+    }
+    outputs.sort();
+    Some(outputs.into_iter().map(|(_, output)| output).collect::<Vec<_>>().join("\n"))
+}
+
+fn read_pipe(index: usize, mut pipe: impl Read + Send + 'static, tx: mpsc::Sender<(usize, String)>) {
+    thread::spawn(move || {
+        let mut output = String::new();
+        let _ = pipe.read_to_string(&mut output);
+        let _ = tx.send((index, output));
+    });
+}
+
+/// Flags mentioned anywhere in `help`, and the subcommands listed under a
+/// heading that mentions commands.
+pub(crate) fn parse_help(help: &str) -> Vocabulary {
+    let mut vocabulary = Vocabulary::default();
+
+    let mut in_commands = false;
+    for line in help.lines() {
+        if !line.starts_with([' ', '\t']) {
+            if !line.trim().is_empty() {
+                in_commands = COMMANDS_HEADING_RE.is_match(line);
+            }
+        } else if in_commands {
+            if let Some(caps) = SUBCOMMAND_LINE_RE.captures(line) {
+                vocabulary.extend(Vocabulary {
+                    subcommands: caps[1].split(',').map(|s| s.trim().to_string()).collect(),
+                    flags: vec![],
+                });
+            }
+        }
+    }
+
+    let mut flags: Vec<String> = FLAG_RE.captures_iter(help).map(|caps| caps[1].to_string()).collect();
+    for caps in NEGATABLE_FLAG_RE.captures_iter(help) {
+        flags.push(format!("--{}", &caps[1]));
+        flags.push(format!("--no-{}", &caps[1]));
+    }
+    vocabulary.extend(Vocabulary { subcommands: vec![], flags });
+    vocabulary
+}
+
+/// Subcommands and flags from the fish completions of `program`, and flags
+/// from its bash completions. Subcommands are only wanted at the top level.
+fn completion_vocabulary(program: &str, top_level: bool) -> Vocabulary {
+    let mut vocabulary = Vocabulary::default();
+
+    let mut fish_dirs: Vec<PathBuf> = dirs::config_dir().map(|d| d.join("fish").join("completions")).into_iter().collect();
+    fish_dirs.extend(["/usr/share/fish/completions", "/usr/share/fish/vendor_completions.d", "/usr/local/share/fish/completions"]
+        .map(PathBuf::from));
+    for dir in fish_dirs {
+        if let Ok(script) = fs::read_to_string(dir.join(format!("{}.fish", program))) {
+            let mut fish = parse_fish_completions(&script);
+            if !top_level {
+                fish.subcommands.clear();
+            }
+            vocabulary.extend(fish);
+            break;
+        }
+    }
+
+    // Bash completions are code rather than data; only the flags are reliable
+    for dir in ["/usr/share/bash-completion/completions", "/etc/bash_completion.d"] {
+        if let Ok(script) = fs::read_to_string(Path::new(dir).join(program)) {
+            let flags = FLAG_RE.captures_iter(&script)
+                .map(|caps| caps[1].to_string())
+                .filter(|f| f.starts_with("--"))
+                .collect();
+            vocabulary.extend(Vocabulary { subcommands: vec![], flags });
+            break;
+        }
+    }
+    vocabulary
+}
+
+/// Reads `complete -c prog -l long -s x -a 'sub1 sub2'` lines. Arguments are
+/// subcommands when offered while no subcommand was given yet.
+pub(crate) fn parse_fish_completions(script: &str) -> Vocabulary {
+    let mut vocabulary = Vocabulary::default();
+
+    for line in script.lines().map(str::trim).filter(|l| l.starts_with("complete ")) {
+        let Ok(args) = split(line) else { continue };
+        let top_level = args.windows(2).any(|w| {
+            matches!(w[0].as_str(), "-n" | "--condition") && w[1].contains("__fish_use_subcommand")
+        });
+
+        let mut found = Vocabulary::default();
+        for pair in args.windows(2) {
+            match pair[0].as_str() {
+                "-l" | "--long-option" => found.flags.push(format!("--{}", pair[1])),
+                "-s" | "--short-option" | "-o" | "--old-option" => found.flags.push(format!("-{}", pair[1])),
+                "-a" | "--arguments" if top_level => {
+                    found.subcommands.extend(pair[1].split_whitespace()
+                        .filter(|w| w.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+                        .map(String::from));
+                }
+                _ => {}
+            }
+        }
+        vocabulary.extend(found);
+    }
+    vocabulary
+}
@@ -0,0 +1,165 @@
+#[cfg(test)]
+mod tests {
+    use crate::rules::unknown_argument::{parse_fish_completions, parse_help, UnknownArgument};
+    use crate::types::Command;
+    use crate::rules::Rule;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    const CLAP_HELP: &str = "Rust's package manager
+
+Usage: cargo [OPTIONS] [COMMAND]
+
+Options:
+  -V, --version             Print version info and exit
+      --list                List installed commands
+  -v, --verbose...          Use verbose output (-vv very verbose/build.rs output)
+
+Commands:
+    build, b    Compile the current package
+    check, c    Analyze the current package and report errors
+    new         Create a new cargo package
+
+See 'cargo help <command>' for more information on a specific command.
+";
+
+    #[test]
+    fn test_parse_help() {
+        let vocabulary = parse_help(CLAP_HELP);
+        assert_eq!(vocabulary.subcommands, vec!["build", "b", "check", "c", "new"]);
+        for flag in ["-V", "--version", "--list", "-v", "--verbose"] {
+            assert!(vocabulary.flags.contains(&flag.to_string()), "{}", flag);
+        }
+        assert!(!vocabulary.flags.contains(&"-output".to_string()));
+    }
+
+    #[test]
+    fn test_parse_help_negatable_and_wrapped_lists() {
+        let vocabulary = parse_help("usage: git commit [--[no-]verify]\n\nAll commands:\n    access, adduser,\n    audit\n");
+        assert_eq!(vocabulary.subcommands, vec!["access", "adduser", "audit"]);
+        assert!(vocabulary.flags.contains(&"--verify".to_string()));
+        assert!(vocabulary.flags.contains(&"--no-verify".to_string()));
+    }
+
+    #[test]
+    fn test_parse_fish_completions() {
+        let vocabulary = parse_fish_completions(r#"
+complete -c tool -n __fish_use_subcommand -f -a 'deploy status' -d 'Subcommands'
+complete -c tool -n '__fish_seen_subcommand_from deploy' -a '(__tool_targets)'
+complete -c tool -l force -s f -d 'Do it anyway'
+"#);
+        assert_eq!(vocabulary.subcommands, vec!["deploy", "status"]);
+        assert_eq!(vocabulary.flags, vec!["--force", "-f"]);
+    }
+
+    #[cfg(unix)]
+    fn write_tool(path: &Path, help: &str) {
+        use std::os::unix::fs::PermissionsExt;
+        fs::write(path, format!("#!/bin/sh\ncat <<'HELP'\n{}\nHELP\n", help)).unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_unknown_flag_from_help_is_cached() {
+        let dir = tempdir().unwrap();
+        let tool = dir.path().join("tool");
+        write_tool(&tool, "Usage: tool [--force] [--dry-run]\n\nCommands:\n  deploy   Ship it\n");
+        let cache = dir.path().join("cache").join("help.toml");
+        let rule = UnknownArgument::new(cache.clone());
+
+        let script = format!("{} deploy --foce", tool.display());
+        let command = Command::new(script.clone(), "".to_string(), "Error: unknown flag: --foce\n".to_string())
+            .with_exit_code(Some(1));
+        assert!(rule.matches(&command));
+        let corrections = rule.generate_corrections(&command);
+        assert_eq!(corrections[0].command, format!("{} deploy --force", tool.display()));
+        assert!(fs::read_to_string(&cache).unwrap().contains("--dry-run"));
+
+        // Served from the cache while the binary is unchanged
+        let rule = UnknownArgument::new(cache.clone());
+        fs::write(&cache, fs::read_to_string(&cache).unwrap().replace("--force", "--forced")).unwrap();
+        assert_eq!(rule.generate_corrections(&command)[0].command, format!("{} deploy --forced", tool.display()));
+
+        // Asked again once it changes
+        write_tool(&tool, "Usage: tool [--force] [--dry-run] [--verbose]\n");
+        let rule = UnknownArgument::new(cache);
+        assert_eq!(rule.generate_corrections(&command)[0].command, format!("{} deploy --force", tool.display()));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_matches_without_running_help() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempdir().unwrap();
+        let (tool, asked) = (dir.path().join("tool"), dir.path().join("asked"));
+        fs::write(&tool, format!("#!/bin/sh\ntouch {}\necho 'Usage: tool [--force]'\n", asked.display())).unwrap();
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+        let rule = UnknownArgument::new(dir.path().join("help.toml"));
+
+        let command = Command::new(format!("{} --foce", tool.display()), "".to_string(), "Error: unknown flag: --foce\n".to_string())
+            .with_exit_code(Some(1));
+        assert!(rule.matches(&command));
+        assert!(!asked.exists());
+
+        let command = Command::new(format!("{} --foce", tool.display()), "".to_string(), "Error: disk full\n".to_string())
+            .with_exit_code(Some(1));
+        assert!(!rule.matches(&command));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_unknown_subcommand_from_help() {
+        let dir = tempdir().unwrap();
+        let tool = dir.path().join("tool");
+        write_tool(&tool, "Commands:\n  deploy   Ship it\n  status   Show it\n");
+        let rule = UnknownArgument::new(dir.path().join("help.toml"));
+
+        let script = format!("{} deplyo prod", tool.display());
+        let command = Command::new(script, "".to_string(), "error: unrecognized subcommand 'deplyo'\n".to_string());
+        let corrections = rule.generate_corrections(&command);
+        assert_eq!(corrections[0].command, format!("{} deploy prod", tool.display()));
+
+        // Left to did_you_mean when the tool suggests something
+        let script = format!("{} deplyo prod", tool.display());
+        let command = Command::new(script, "".to_string(),
+            "error: unrecognized subcommand 'deplyo'\n\n  tip: a similar subcommand exists: 'deploy'\n".to_string());
+        assert!(rule.generate_corrections(&command).is_empty());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_help_never_gets_positional_arguments() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempdir().unwrap();
+        let tool = dir.path().join("deploy.sh");
+        let log = dir.path().join("args.log");
+        fs::write(&tool, format!("#!/bin/sh\necho \"$@\" >> {}\necho 'Usage: deploy.sh [--force]'\n", log.display())).unwrap();
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+        let rule = UnknownArgument::new(dir.path().join("help.toml"));
+
+        let script = format!("{} prod --foce", tool.display());
+        let command = Command::new(script, "".to_string(), "unknown option: --foce\n".to_string());
+        assert_eq!(rule.generate_corrections(&command)[0].command, format!("{} prod --force", tool.display()));
+        assert_eq!(fs::read_to_string(&log).unwrap(), "--help\n");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_help_left_running_in_background_times_out() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempdir().unwrap();
+        let tool = dir.path().join("tool");
+        // Exits straight away, but a background process keeps stdout open
+        fs::write(&tool, "#!/bin/sh\necho 'Usage: tool [--force]'\nsleep 5 &\n").unwrap();
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+        let rule = UnknownArgument::new(dir.path().join("help.toml"));
+
+        let script = format!("{} --foce", tool.display());
+        let command = Command::new(script, "".to_string(), "unknown flag: --foce\n".to_string());
+        let start = std::time::Instant::now();
+        assert!(rule.generate_corrections(&command).is_empty());
+        assert!(start.elapsed() < std::time::Duration::from_secs(3));
+    }
+}
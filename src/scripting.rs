@@ -1,15 +1,44 @@
 use rhai::{Engine, Scope, AST};
 use crate::types::{Command, Correction};
 use crate::rules::Rule;
-use std::sync::Arc;
+use crate::paths::rule_files;
+use crate::executables::{self, mtime};
+use crate::messages::{self, Message};
+use anyhow::{Result, anyhow};
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
 use std::fmt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::fs;
 
+// One engine for every scripted rule. Building an Engine registers all the
+// standard packages, which is the expensive part of loading a script.
+static ENGINE: Lazy<Arc<Engine>> = Lazy::new(|| {
+    let mut engine = Engine::new();
+    engine.register_fn("command_exists", |name: &str| executables::command_exists(name));
+    engine.register_fn("has_message", |text: &str, name: &str| {
+        Message::from_name(name).is_some_and(|message| messages::contains(text, message))
+    });
+    Arc::new(engine)
+});
+
+// Compiled scripts keyed by their text, so the same script reached twice in
+// one run (e.g. from two rule directories) is only parsed once.
+static AST_CACHE: Lazy<Mutex<HashMap<String, AST>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub fn shared_engine() -> Arc<Engine> {
+    ENGINE.clone()
+}
+
 #[derive(Clone)]
 pub struct RhaiRule {
     engine: Arc<Engine>,
-    ast: AST,
+    /// Compiled on first use from `path` for scripts the index already
+    /// validated, so that happens on the rule's own thread.
+    ast: Arc<OnceCell<Option<AST>>>,
+    path: Option<PathBuf>,
     name: String,
     priority: usize,
 }
@@ -24,17 +53,44 @@ impl fmt::Debug for RhaiRule {
 }
 
 impl RhaiRule {
-    pub fn new(name: String, script: &str, priority: usize) -> Self {
-        let engine = Engine::new();
-        // In a real app we might want to handle compilation errors gracefully, but for now we expect valid scripts
-        let ast = engine.compile(script).expect("Failed to compile rhai script");
+    pub fn new(name: String, script: &str, priority: usize) -> Result<Self> {
+        let ast = compile_cached(script)?;
+        Ok(Self::from_ast(name, ast, priority))
+    }
+
+    pub fn from_ast(name: String, ast: AST, priority: usize) -> Self {
         Self {
-            engine: Arc::new(engine),
-            ast,
+            engine: shared_engine(),
+            ast: Arc::new(OnceCell::with_value(Some(ast))),
+            path: None,
             name,
             priority,
         }
     }
+
+    /// A rule compiled from `path` when it's first evaluated.
+    fn lazy(name: String, path: PathBuf, priority: usize) -> Self {
+        Self {
+            engine: shared_engine(),
+            ast: Arc::new(OnceCell::new()),
+            path: Some(path),
+            name,
+            priority,
+        }
+    }
+
+    #[cfg(test)]
+    fn is_compiled(&self) -> bool {
+        self.ast.get().is_some()
+    }
+
+    /// `None` if the script changed since it was indexed and no longer compiles.
+    fn ast(&self) -> Option<&AST> {
+        self.ast.get_or_init(|| {
+            let path = self.path.as_ref()?;
+            fs::read_to_string(path).ok().and_then(|script| compile_cached(&script).ok())
+        }).as_ref()
+    }
 }
 
 impl Rule for RhaiRule {
@@ -43,22 +99,16 @@ impl Rule for RhaiRule {
     }
 
     fn matches(&self, command: &Command) -> bool {
-        let mut scope = Scope::new();
-        scope.push("script", command.script.clone());
-        scope.push("stdout", command.stdout.clone());
-        scope.push("stderr", command.stderr.clone());
-
-        let result: bool = self.engine.call_fn(&mut scope, &self.ast, "matches", ()).unwrap_or(false);
+        let Some(ast) = self.ast() else { return false };
+        let mut scope = command_scope(command);
+        let result: bool = self.engine.call_fn(&mut scope, ast, "matches", ()).unwrap_or(false);
         result
     }
 
     fn generate_corrections(&self, command: &Command) -> Vec<Correction> {
-        let mut scope = Scope::new();
-        scope.push("script", command.script.clone());
-        scope.push("stdout", command.stdout.clone());
-        scope.push("stderr", command.stderr.clone());
-
-        let result: String = match self.engine.call_fn(&mut scope, &self.ast, "get_new_command", ()) {
+        let Some(ast) = self.ast() else { return vec![] };
+        let mut scope = command_scope(command);
+        let result: String = match self.engine.call_fn(&mut scope, ast, "get_new_command", ()) {
             Ok(s) => s,
             Err(_) => return vec![],
         };
@@ -67,23 +117,205 @@ impl Rule for RhaiRule {
     }
 }
 
-pub fn load_rhai_rules(dir: &Path) -> Vec<RhaiRule> {
-    let mut rules = Vec::new();
+/// The variables a script sees. The raw output is at most
+/// [`MAX_OUTPUT`](crate::output::MAX_OUTPUT) bytes like the normalized one.
+fn command_scope(command: &Command) -> Scope<'static> {
+    let mut scope = Scope::new();
+    scope.push("script", command.script.clone());
+    scope.push("stdout", command.stdout.clone());
+    scope.push("stderr", command.stderr.clone());
+    scope.push("raw_stdout", command.raw_stdout.clone());
+    scope.push("raw_stderr", command.raw_stderr.clone());
+    scope
+}
+
+fn compile_cached(script: &str) -> Result<AST> {
+    let mut cache = AST_CACHE.lock().unwrap();
+    if let Some(ast) = cache.get(script) {
+        return Ok(ast.clone());
+    }
+    let ast = ENGINE.compile(script).map_err(|e| anyhow!("{}", e))?;
+    cache.insert(script.to_string(), ast.clone());
+    Ok(ast)
+}
+
+/// Which scripts compiled on an earlier run, by path, mtime and size.
+///
+/// Rhai has no serialized AST form, so the compile itself can't be cached.
+/// Instead, scripts that are unchanged since they last compiled aren't read at
+/// startup; each is compiled on its rule's thread when first evaluated, in
+/// parallel with the other rules rather than one after another before them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ScriptIndex {
+    #[serde(default)]
+    scripts: BTreeMap<String, IndexedScript>,
+    #[serde(skip)]
+    dirty: bool,
+}
 
-    if let Ok(entries) = fs::read_dir(dir) {
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let path = entry.path();
-                if path.extension().map_or(false, |ext| ext == "rhai") {
-                    if let Ok(content) = fs::read_to_string(&path) {
-                        let name = path.file_stem().unwrap().to_string_lossy().to_string();
-                        // Priority default to 100 for now
-                        rules.push(RhaiRule::new(name, &content, 100));
-                    }
-                }
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct IndexedScript {
+    /// Nanoseconds since the epoch.
+    mtime: u64,
+    len: u64,
+    /// The compile error, for scripts that didn't compile.
+    error: Option<String>,
+}
+
+impl ScriptIndex {
+    pub fn default_path() -> PathBuf {
+        let cache_dir = dirs::cache_dir().unwrap_or_else(|| PathBuf::from("."));
+        cache_dir.join("ffs").join("scripts.toml")
+    }
+
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// The rule for the script at `path`: compiled now if it's new or changed,
+    /// otherwise left to compile on first use.
+    fn rule(&mut self, path: &Path) -> Result<RhaiRule> {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        let key = path.to_string_lossy().to_string();
+        let stamp = mtime(path).zip(fs::metadata(path).ok().map(|m| m.len()));
+
+        if let (Some(indexed), Some((mtime, len))) = (self.scripts.get(&key), stamp) {
+            if indexed.mtime == mtime && indexed.len == len {
+                return match &indexed.error {
+                    Some(error) => Err(anyhow!("{}", error)),
+                    // Priority default to 100 for now
+                    None => Ok(RhaiRule::lazy(name, path.to_path_buf(), 100)),
+                };
             }
         }
+
+        let compiled = fs::read_to_string(path).map_err(anyhow::Error::from).and_then(|s| compile_cached(&s));
+        if let Some((mtime, len)) = stamp {
+            let error = compiled.as_ref().err().map(|e| e.to_string());
+            self.scripts.insert(key, IndexedScript { mtime, len, error });
+            self.dirty = true;
+        }
+        Ok(RhaiRule::from_ast(name, compiled?, 100))
+    }
+}
+
+/// Loads `.rhai` rules from `dirs`, later directories shadowing earlier ones,
+/// using and updating the [`ScriptIndex`] in the cache directory.
+pub fn load_rhai_rules(dirs: &[PathBuf]) -> Vec<RhaiRule> {
+    let path = ScriptIndex::default_path();
+    let mut index = ScriptIndex::load(&path);
+    let rules = load_rhai_rules_indexed(dirs, &mut index);
+    let _ = index.save(&path);
+    rules
+}
+
+pub fn load_rhai_rules_indexed(dirs: &[PathBuf], index: &mut ScriptIndex) -> Vec<RhaiRule> {
+    let files = rule_files(dirs, "rhai");
+    let mut rules = Vec::new();
+
+    for path in &files {
+        match index.rule(path) {
+            Ok(rule) => rules.push(rule),
+            Err(e) => eprintln!("Failed to load rule {}: {}", path.display(), e),
+        }
     }
 
+    // Forget scripts that were deleted or are now shadowed
+    let before = index.scripts.len();
+    index.scripts.retain(|key, _| files.iter().any(|f| f.to_string_lossy() == key.as_str()));
+    index.dirty |= index.scripts.len() != before;
     rules
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const RULE: &str = r#"
+        fn matches() { script.starts_with("gti") }
+        fn get_new_command() { script.replace("gti", "git"); script }
+    "#;
+
+    #[test]
+    fn test_rhai_rule() {
+        let rule = RhaiRule::new("gti".to_string(), RULE, 100).unwrap();
+        let command = Command::new("gti status".to_string(), "".to_string(), "".to_string());
+
+        assert!(rule.matches(&command));
+        let corrections = rule.generate_corrections(&command);
+        assert_eq!(corrections.len(), 1);
+        assert_eq!(corrections[0].command, "git status");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_rhai_command_exists() {
+        let script = r#"
+            fn matches() { command_exists("/bin/sh") && !command_exists("/nonexistent/sh") }
+            fn get_new_command() { script }
+        "#;
+        let rule = RhaiRule::new("exists".to_string(), script, 100).unwrap();
+        assert!(rule.matches(&Command::new("ls".to_string(), "".to_string(), "".to_string())));
+    }
+
+    #[test]
+    fn test_rhai_rule_compile_error() {
+        assert!(RhaiRule::new("broken".to_string(), "fn matches( {", 100).is_err());
+    }
+
+    #[test]
+    fn test_load_rhai_rules_skips_broken_scripts() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("gti.rhai"), RULE).unwrap();
+        fs::write(dir.path().join("broken.rhai"), "fn matches( {").unwrap();
+
+        let rules = load_rhai_rules_indexed(&[dir.path().to_path_buf()], &mut ScriptIndex::default());
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].name(), "gti");
+    }
+
+    #[test]
+    fn test_script_index_defers_unchanged_scripts() {
+        let dir = tempdir().unwrap();
+        let index_path = dir.path().join("cache").join("scripts.toml");
+        let rules_dir = dir.path().join("rules");
+        fs::create_dir_all(&rules_dir).unwrap();
+        fs::write(rules_dir.join("gti.rhai"), RULE).unwrap();
+        fs::write(rules_dir.join("broken.rhai"), "fn matches( {").unwrap();
+        let dirs = [rules_dir.clone()];
+
+        let mut index = ScriptIndex::load(&index_path);
+        let rules = load_rhai_rules_indexed(&dirs, &mut index);
+        assert!(rules[0].is_compiled());
+        index.save(&index_path).unwrap();
+
+        // Unchanged: known good, compiled when first evaluated
+        let mut index = ScriptIndex::load(&index_path);
+        let rules = load_rhai_rules_indexed(&dirs, &mut index);
+        assert_eq!(rules.len(), 1);
+        assert!(!rules[0].is_compiled());
+        assert!(!index.dirty);
+        assert!(rules[0].matches(&Command::new("gti status".to_string(), "".to_string(), "".to_string())));
+
+        // Changed: validated again at load
+        fs::write(rules_dir.join("gti.rhai"), "fn matches( { // now broken").unwrap();
+        let rules = load_rhai_rules_indexed(&dirs, &mut index);
+        assert!(rules.is_empty());
+        assert!(index.dirty);
+    }
+}
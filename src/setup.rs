@@ -0,0 +1,132 @@
+use crate::shells::ShellConfiguration;
+use anyhow::Result;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub const BACKUP_SUFFIX: &str = ".ffs-backup";
+
+#[derive(Debug, PartialEq)]
+pub enum SetupOutcome {
+    /// The startup file already loads the function.
+    AlreadyConfigured,
+    /// The line was appended; `backup` is the copy of the previous file, if
+    /// there was one.
+    Installed { backup: Option<PathBuf> },
+}
+
+/// Whether the startup file at `path` already loads the function, whatever
+/// shell or alias name it was set up with.
+pub fn is_configured(path: &Path) -> bool {
+    fs::read_to_string(path).is_ok_and(|contents| {
+        contents.lines()
+            .map(str::trim_start)
+            .any(|line| !line.starts_with('#') && line.contains("ffs --alias"))
+    })
+}
+
+/// Appends the configuration line to the startup file, after copying the file
+/// to `<path>.ffs-backup`, or `<path>.ffs-backup-<n>` if earlier backups exist
+/// so they're never overwritten. Does nothing if the file already loads ffs.
+pub fn install(config: &ShellConfiguration) -> Result<SetupOutcome> {
+    if is_configured(&config.path) {
+        return Ok(SetupOutcome::AlreadyConfigured);
+    }
+
+    let backup = if config.path.exists() {
+        let backup = backup_path(&config.path);
+        fs::copy(&config.path, &backup)?;
+        Some(backup)
+    } else {
+        if let Some(parent) = config.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        None
+    };
+
+    // Leave a blank line after whatever is already there
+    let separator = match fs::read(&config.path) {
+        Ok(bytes) if bytes.is_empty() => "",
+        Ok(bytes) if bytes.ends_with(b"\n") => "\n",
+        Ok(_) => "\n\n",
+        Err(_) => "",
+    };
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(&config.path)?;
+    write!(file, "{}# Added by `ffs setup`\n{}\n", separator, config.content)?;
+
+    Ok(SetupOutcome::Installed { backup })
+}
+
+/// The first of `<path>.ffs-backup`, `<path>.ffs-backup-1`, … that doesn't exist.
+fn backup_path(path: &Path) -> PathBuf {
+    let with_suffix = |suffix: String| {
+        let mut backup = path.as_os_str().to_os_string();
+        backup.push(suffix);
+        PathBuf::from(backup)
+    };
+    std::iter::once(BACKUP_SUFFIX.to_string())
+        .chain((1..).map(|n| format!("{}-{}", BACKUP_SUFFIX, n)))
+        .map(with_suffix)
+        .find(|backup| !backup.exists())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_install_is_idempotent() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(".bashrc");
+        fs::write(&path, "export EDITOR=vi").unwrap();
+        let config = ShellConfiguration { content: r#"eval "$(ffs --alias bash)""#.to_string(), path: path.clone() };
+
+        let backup = dir.path().join(".bashrc.ffs-backup");
+        assert_eq!(install(&config).unwrap(), SetupOutcome::Installed { backup: Some(backup.clone()) });
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "export EDITOR=vi");
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "export EDITOR=vi\n\n# Added by `ffs setup`\neval \"$(ffs --alias bash)\"\n"
+        );
+
+        assert_eq!(install(&config).unwrap(), SetupOutcome::AlreadyConfigured);
+        assert_eq!(fs::read_to_string(&path).unwrap().matches("ffs --alias").count(), 1);
+    }
+
+    #[test]
+    fn test_install_keeps_earlier_backups() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(".bashrc");
+        let config = ShellConfiguration { content: r#"eval "$(ffs --alias bash)""#.to_string(), path: path.clone() };
+
+        fs::write(&path, "original").unwrap();
+        install(&config).unwrap();
+        // The user removed the line, then ran setup again
+        fs::write(&path, "edited").unwrap();
+        let second = dir.path().join(".bashrc.ffs-backup-1");
+        assert_eq!(install(&config).unwrap(), SetupOutcome::Installed { backup: Some(second.clone()) });
+
+        assert_eq!(fs::read_to_string(dir.path().join(".bashrc.ffs-backup")).unwrap(), "original");
+        assert_eq!(fs::read_to_string(&second).unwrap(), "edited");
+    }
+
+    #[test]
+    fn test_install_creates_missing_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("fish").join("config.fish");
+        let config = ShellConfiguration { content: "ffs --alias fish | source".to_string(), path: path.clone() };
+
+        assert_eq!(install(&config).unwrap(), SetupOutcome::Installed { backup: None });
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# Added by `ffs setup`\nffs --alias fish | source\n");
+    }
+
+    #[test]
+    fn test_commented_out_line_is_not_configured() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(".zshrc");
+        fs::write(&path, "# eval \"$(ffs --alias zsh)\"\n").unwrap();
+        assert!(!is_configured(&path));
+    }
+}
@@ -1,34 +1,68 @@
-use super::Shell;
+use super::{AliasOptions, Shell, ShellConfiguration, parse_posix_aliases, posix_output_log};
+use std::collections::HashMap;
 
 pub struct Bash;
 
 impl Shell for Bash {
-    fn app_alias(&self, alias_name: &str) -> String {
+    fn name(&self) -> &'static str {
+        "bash"
+    }
+
+    fn app_alias(&self, alias_name: &str, options: &AliasOptions) -> String {
+        // `history -s` drops the last entry, the call to this function, before
+        // adding the correction. `$?` is still the failed command's status on
+        // entry.
+        let history = if options.alter_history { "\n        history -s \"$TF_CMD\";" } else { "" };
+        let output_log = if options.instant { posix_output_log("bash") } else { String::new() };
         format!(
-            r#"
+            r#"{output_log}
 function {alias_name} () {{
-    TF_PYTHONIOENCODING=$PYTHONIOENCODING;
-    export TF_SHELL=bash;
-    export TF_ALIAS={alias_name};
-    export TF_SHELL_ALIASES=$(alias);
-    export TF_HISTORY=$(fc -ln -10);
-    export PYTHONIOENCODING=utf-8;
+    local TF_EXIT_CODE=$? TF_CMD;
     TF_CMD=$(
-        ffs "$@"
-    );
-    BLOCK_RES=$?;
-    if [ $BLOCK_RES -eq 0 ]; then
+        TF_SHELL=bash TF_ALIAS={alias_name} TF_EXIT_CODE=$TF_EXIT_CODE \
+        TF_SHELL_ALIASES="$(alias)" TF_SHELL_FUNCTIONS="$(compgen -A function)" \
+        TF_HISTORY="$(fc -ln -10)" command ffs{flags} "$@"
+    ) || return;
+    if [ -n "$TF_CMD" ]; then{history}
         eval "$TF_CMD";
     fi
-    unset TF_HISTORY;
-    export PYTHONIOENCODING=$TF_PYTHONIOENCODING;
-    history -s $TF_CMD;
 }}
 "#,
-            alias_name = alias_name
+            output_log = output_log,
+            alias_name = alias_name,
+            flags = options.flags(),
+            history = history
         )
     }
 
+    fn keybinding(&self, alias_name: &str) -> Option<String> {
+        Some(format!(
+            r#"
+__ffs_fix_buffer () {{
+    local TF_CMD;
+    TF_CMD=$(
+        TF_SHELL=bash TF_ALIAS={alias_name} TF_SHELL_ALIASES="$(alias)" \
+        TF_SHELL_FUNCTIONS="$(compgen -A function)" TF_HISTORY="$(fc -ln -1)" command ffs --no-history </dev/tty
+    );
+    if [ -n "$TF_CMD" ]; then
+        READLINE_LINE=$TF_CMD;
+        READLINE_POINT=${{#READLINE_LINE}};
+    fi
+}}
+bind -x '"\e\e": __ffs_fix_buffer'
+"#,
+            alias_name = alias_name
+        ))
+    }
+
+    fn how_to_configure(&self) -> Option<ShellConfiguration> {
+        // Login shells on macOS read ~/.bash_profile instead of ~/.bashrc
+        let home = dirs::home_dir()?;
+        let profile = home.join(".bash_profile");
+        let path = if cfg!(target_os = "macos") && profile.exists() { profile } else { home.join(".bashrc") };
+        Some(ShellConfiguration { content: r#"eval "$(ffs --alias bash)""#.to_string(), path })
+    }
+
     fn get_history_file_name(&self) -> String {
         std::env::var("HISTFILE").unwrap_or_else(|_| {
             dirs::home_dir()
@@ -36,4 +70,147 @@ function {alias_name} () {{
                 .unwrap_or_else(|| ".bash_history".to_string())
         })
     }
+
+    fn parse_tf_history(&self, output: &str) -> Vec<String> {
+        // `fc -l` prints each entry as `[number]<TAB><SPACE>command`; further
+        // lines of a multi-line entry (heredocs, with `lithist`) have no prefix,
+        // though a `<<-` heredoc body can start with a tab of its own.
+        let mut entries: Vec<String> = Vec::new();
+        for line in output.lines() {
+            let number_len = line.find(|c: char| !c.is_ascii_digit()).unwrap_or(line.len());
+            match line[number_len..].strip_prefix("\t ") {
+                Some(command) => entries.push(command.to_string()),
+                None => match entries.last_mut() {
+                    Some(entry) => {
+                        entry.push('\n');
+                        entry.push_str(line);
+                    }
+                    None => entries.push(line.to_string()),
+                },
+            }
+        }
+
+        entries.into_iter()
+            .map(|e| e.trim().to_string())
+            .filter(|e| !e.is_empty())
+            .collect()
+    }
+
+    fn parse_history(&self, contents: &str) -> Vec<String> {
+        // With HISTTIMEFORMAT set each entry is preceded by a `#<timestamp>` line
+        contents.lines()
+            .filter(|line| !line.trim().is_empty() && !is_timestamp_line(line))
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn parse_aliases(&self, output: &str) -> HashMap<String, String> {
+        parse_posix_aliases(output)
+    }
+}
+
+fn is_timestamp_line(line: &str) -> bool {
+    line.strip_prefix('#').is_some_and(|ts| !ts.is_empty() && ts.chars().all(|c| c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shells::{all_alias_options, assert_snapshot};
+    use std::process::Command;
+
+    #[test]
+    fn test_app_alias_syntax() {
+        for options in [AliasOptions::default(), AliasOptions { alter_history: false, ..all_alias_options() }] {
+            let alias = Bash.app_alias("fuck", &options);
+            assert_eq!(alias.contains("history -s \"$TF_CMD\""), options.alter_history);
+
+            let status = Command::new("bash").args(["-n", "-c", &alias]).status().unwrap();
+            assert!(status.success());
+        }
+    }
+
+    #[test]
+    fn test_snapshots() {
+        assert_snapshot("bash_alias", &Bash.app_alias("ffs", &AliasOptions::default()));
+        assert_snapshot("bash_alias_all_options", &Bash.app_alias("fuck", &all_alias_options()));
+        assert_snapshot("bash_keybinding", &Bash.keybinding("ffs").unwrap());
+    }
+
+    #[test]
+    fn test_shellcheck() {
+        // Only runs where shellcheck is installed
+        let shellcheck = match which::which("shellcheck") {
+            Ok(p) => p,
+            Err(_) => return,
+        };
+
+        for script in [Bash.app_alias("fuck", &all_alias_options()), Bash.keybinding("fuck").unwrap()] {
+            let status = Command::new(&shellcheck)
+                .args(["--shell=bash", "-"])
+                .stdin(std::process::Stdio::piped())
+                .spawn()
+                .and_then(|mut child| {
+                    use std::io::Write;
+                    child.stdin.take().unwrap().write_all(script.as_bytes())?;
+                    child.wait()
+                })
+                .unwrap();
+            assert!(status.success());
+        }
+    }
+
+    #[test]
+    fn test_quote_roundtrip() {
+        let script = "echo 'it''s' \"$HOME\" `x` \\ !";
+        let output = Command::new("bash")
+            .args(["-c", &format!("printf %s {}", Bash.quote(script))])
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), script);
+    }
+
+    #[test]
+    fn test_keybinding_syntax() {
+        let binding = Bash.keybinding("fuck").unwrap();
+        let status = Command::new("bash").args(["-n", "-c", &binding]).status().unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn test_parse_tf_history() {
+        // Captured from `fc -ln -10` in bash 5.2
+        let output = "\t HISTFILE=/dev/null\n\t ls /nonexistent\n\t cat <<END\nhello\nEND\n\n\t echo a   b\n\t for i in 1 2; do   echo $i; done";
+        assert_eq!(Bash.parse_tf_history(output), vec![
+            "HISTFILE=/dev/null",
+            "ls /nonexistent",
+            "cat <<END\nhello\nEND",
+            "echo a   b",
+            "for i in 1 2; do   echo $i; done",
+        ]);
+
+        // `fc -l` keeps the event numbers
+        let output = "1\t git psuh\n2\t   ls -la  ";
+        assert_eq!(Bash.parse_tf_history(output), vec!["git psuh", "ls -la"]);
+
+        // A tab-indented `<<-` body stays in its entry
+        let output = "\t cat <<-END\n\thello\n\tEND\n\t ls";
+        assert_eq!(Bash.parse_tf_history(output), vec!["cat <<-END\n\thello\n\tEND", "ls"]);
+    }
+
+    #[test]
+    fn test_parse_history() {
+        let history = Bash.parse_history("ls\n#1700000000\ngit psuh\n\n# not a timestamp\n");
+        assert_eq!(history, vec!["ls", "git psuh", "# not a timestamp"]);
+    }
+
+    #[test]
+    fn test_parse_aliases() {
+        let output = "alias g='git'\nalias ll='ls -l'\nalias say='echo '\\''hi'\\'''\n";
+        let aliases = Bash.parse_aliases(output);
+        assert_eq!(aliases.len(), 3);
+        assert_eq!(aliases["g"], "git");
+        assert_eq!(aliases["ll"], "ls -l");
+        assert_eq!(aliases["say"], "echo 'hi'");
+    }
 }
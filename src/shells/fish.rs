@@ -1,32 +1,95 @@
-use super::Shell;
+use super::{AliasOptions, HISTORY_SEPARATOR, Shell, ShellConfiguration, unix_timestamp};
+use std::collections::HashMap;
 
 pub struct Fish;
 
 impl Shell for Fish {
-    fn app_alias(&self, alias_name: &str) -> String {
-        format!(
+    fn name(&self) -> &'static str {
+        "fish"
+    }
+
+    fn app_alias(&self, alias_name: &str, options: &AliasOptions) -> String {
+        // ffs has already appended the correction to the history file
+        let history = if options.alter_history { "\n        builtin history merge" } else { "" };
+        let output_log = if options.instant {
             r#"
+if not set -q TF_OUTPUT_LOG; and status is-interactive; and command -q script
+    set -l dir /tmp
+    set -q TMPDIR; and set dir $TMPDIR
+    set -q XDG_RUNTIME_DIR; and set dir $XDG_RUNTIME_DIR
+    set -l old_umask (umask)
+    umask 077
+    set -l log (mktemp $dir/ffs-XXXXXX)
+    umask $old_umask
+    if test -n "$log"
+        set -gx TF_OUTPUT_LOG $log
+        script -qf -c fish $TF_OUTPUT_LOG
+        set -l script_status $status
+        rm -f $TF_OUTPUT_LOG
+        exit $script_status
+    end
+end
+"#
+        } else {
+            ""
+        };
+        format!(
+            r#"{output_log}
 function {alias_name}
-    set -x TF_PYTHONIOENCODING $PYTHONIOENCODING
-    set -x TF_SHELL fish
-    set -x TF_ALIAS {alias_name}
-    set -x TF_SHELL_ALIASES (alias)
-    set -x TF_HISTORY (history -n 10)
-    set -x PYTHONIOENCODING utf-8
-
-    set -l TF_CMD (ffs $argv)
+    set -l TF_EXIT_CODE $status
+    set -l TF_CMD (TF_SHELL=fish TF_ALIAS={alias_name} TF_EXIT_CODE=$TF_EXIT_CODE \
+        TF_SHELL_ALIASES=(alias | string collect) \
+        TF_SHELL_FUNCTIONS=(functions --names | string collect) \
+        TF_HISTORY=(builtin history -z -n 10 | string split0 | string join \x1e) \
+        command ffs{flags} $argv | string collect)
 
-    if test -n "$TF_CMD"
+    if test -n "$TF_CMD"{history}
         eval $TF_CMD
-        history --merge ^ /dev/null
     end
+end
+"#,
+            output_log = output_log,
+            alias_name = alias_name,
+            flags = options.flags(),
+            history = history
+        )
+    }
 
-    set -e TF_HISTORY
-    set -x PYTHONIOENCODING $TF_PYTHONIOENCODING
+    fn keybinding(&self, alias_name: &str) -> Option<String> {
+        Some(format!(
+            r#"
+function __ffs_fix_buffer
+    set -l TF_CMD (TF_SHELL=fish TF_ALIAS={alias_name} TF_SHELL_ALIASES=(alias | string collect) \
+        TF_SHELL_FUNCTIONS=(functions --names | string collect) TF_HISTORY=$history[1] \
+        command ffs --no-history </dev/tty | string collect)
+    if test -n "$TF_CMD"
+        commandline --replace -- $TF_CMD
+    end
+    commandline --function repaint
 end
+bind \e\e __ffs_fix_buffer
 "#,
             alias_name = alias_name
-        )
+        ))
+    }
+
+    fn quote(&self, s: &str) -> String {
+        // Inside single quotes fish only treats `\\` and `\'` specially
+        format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+    }
+
+    fn or(&self, first: &str, second: &str) -> String {
+        format!("{}; or {}", first, second)
+    }
+
+    fn how_to_configure(&self) -> Option<ShellConfiguration> {
+        let dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(std::path::PathBuf::from)
+            .or_else(|| dirs::home_dir().map(|h| h.join(".config")))?;
+        Some(ShellConfiguration {
+            content: "ffs --alias fish | source".to_string(),
+            path: dir.join("fish").join("config.fish"),
+        })
     }
 
     fn get_history_file_name(&self) -> String {
@@ -38,4 +101,108 @@ end
                 .unwrap_or_else(|| ".local/share/fish/fish_history".to_string())
             })
     }
+
+    fn writes_history_file(&self) -> bool {
+        true
+    }
+
+    fn get_history_line(&self, command_script: &str) -> String {
+        let escaped = command_script.replace('\\', "\\\\").replace('\n', "\\n");
+        format!("- cmd: {}\n  when: {}\n", escaped, unix_timestamp())
+    }
+
+    fn parse_tf_history(&self, output: &str) -> Vec<String> {
+        // `history` lists newest first; entries are joined with the separator
+        // since they may span several lines.
+        output.split(HISTORY_SEPARATOR)
+            .rev()
+            .map(|entry| entry.trim().to_string())
+            .filter(|entry| !entry.is_empty())
+            .collect()
+    }
+
+    fn parse_history(&self, contents: &str) -> Vec<String> {
+        // Entries are `- cmd: <escaped>` followed by indented `when:`/`paths:`
+        contents.lines()
+            .filter_map(|line| line.strip_prefix("- cmd: "))
+            .map(unescape)
+            .collect()
+    }
+
+    fn parse_aliases(&self, output: &str) -> HashMap<String, String> {
+        // `alias` prints `alias name 'value'` per line
+        output.lines()
+            .filter_map(|line| {
+                let words = shell_words::split(line.trim()).ok()?;
+                match words.as_slice() {
+                    [kw, name, value, ..] if kw == "alias" => Some((name.clone(), value.clone())),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('\\')) => { out.push('\\'); chars.next(); }
+            ('\\', Some('n')) => { out.push('\n'); chars.next(); }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shells::{all_alias_options, assert_snapshot};
+
+    #[test]
+    fn test_history_line() {
+        let line = Fish.get_history_line("echo a\\b\necho c");
+        assert!(line.starts_with("- cmd: echo a\\\\b\\necho c\n  when: "));
+    }
+
+    #[test]
+    fn test_parse_tf_history() {
+        let output = "ffs\x1egit psuh\x1efor i in 1 2\n    echo $i\nend\x1els";
+        assert_eq!(Fish.parse_tf_history(output), vec!["ls", "for i in 1 2\n    echo $i\nend", "git psuh", "ffs"]);
+    }
+
+    #[test]
+    fn test_parse_history() {
+        let contents = "- cmd: ls\n  when: 1700000000\n- cmd: echo a\\\\b\\necho c\n  when: 1700000001\n  paths:\n    - a\n";
+        let history = Fish.parse_history(contents);
+        assert_eq!(history, vec!["ls", "echo a\\b\necho c"]);
+    }
+
+    #[test]
+    fn test_parse_history_roundtrip() {
+        let command = "printf 'a\\n'\necho done";
+        assert_eq!(Fish.parse_history(&Fish.get_history_line(command)), vec![command]);
+    }
+
+    #[test]
+    fn test_parse_aliases() {
+        let aliases = Fish.parse_aliases("alias g git\nalias ll 'ls -l'\n");
+        assert_eq!(aliases.len(), 2);
+        assert_eq!(aliases["g"], "git");
+        assert_eq!(aliases["ll"], "ls -l");
+    }
+
+    #[test]
+    fn test_snapshots() {
+        assert_snapshot("fish_alias", &Fish.app_alias("ffs", &AliasOptions::default()));
+        assert_snapshot("fish_alias_all_options", &Fish.app_alias("fuck", &all_alias_options()));
+        assert_snapshot("fish_keybinding", &Fish.keybinding("ffs").unwrap());
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(Fish.quote("echo 'a' \\ $b"), "'echo \\'a\\' \\\\ $b'");
+    }
 }
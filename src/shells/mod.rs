@@ -1,13 +1,419 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Settings baked into the generated shell function.
+#[derive(Debug, Clone)]
+pub struct AliasOptions {
+    /// Put the correction into the session's history in place of the call to
+    /// the shell function.
+    pub alter_history: bool,
+    /// Run the first correction without asking (`--yeah`).
+    pub yeah: bool,
+    /// Offer to fix the correction as well if it fails (`--repeat`).
+    pub repeat: bool,
+    /// Log the session's output so ffs can read the failed command's output
+    /// instead of re-running it (`--instant`). Only bash, zsh and fish set up
+    /// the log; elsewhere the command is re-run as usual.
+    pub instant: bool,
+}
+
+impl Default for AliasOptions {
+    fn default() -> Self {
+        Self { alter_history: true, yeah: false, repeat: false, instant: false }
+    }
+}
+
+impl AliasOptions {
+    /// The ffs flags the function passes before the user's arguments, each
+    /// preceded by a space.
+    pub fn flags(&self) -> String {
+        [(self.yeah, " --yeah"), (self.repeat, " --repeat"), (self.instant, " --instant")]
+            .iter()
+            .filter(|(enabled, _)| *enabled)
+            .map(|(_, flag)| *flag)
+            .collect()
+    }
+}
+
+/// Whether `name` can be used as the function name in every supported shell.
+pub fn is_valid_alias_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Where and how to load the shell function on startup.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShellConfiguration {
+    /// The line to add to the startup file.
+    pub content: String,
+    /// The shell's startup file.
+    pub path: PathBuf,
+}
 
 pub trait Shell {
-    fn app_alias(&self, alias_name: &str) -> String;
+    /// The name used for `TF_SHELL` and `--alias`, which is also the executable.
+    fn name(&self) -> &'static str;
+    fn app_alias(&self, alias_name: &str, options: &AliasOptions) -> String;
     fn get_history_file_name(&self) -> String;
+
+    /// The startup file line that defines the function, or `None` if the
+    /// shell can't generate it on startup and needs manual setup.
+    fn how_to_configure(&self) -> Option<ShellConfiguration> {
+        None
+    }
+
+    /// Code binding Esc Esc to fix the previous command by putting the chosen
+    /// correction in the editing buffer rather than running it. Text being
+    /// typed is never re-run, since it was never submitted. ffs is called with
+    /// `--no-history`, as the correction only reaches history if the user runs
+    /// it. `None` if the shell's line editor can't be scripted that way.
+    fn keybinding(&self, _alias_name: &str) -> Option<String> {
+        None
+    }
+
+    /// Whether ffs has to append the correction to the history file itself, for
+    /// shells whose function can only pick up history by re-reading the file.
+    fn writes_history_file(&self) -> bool {
+        false
+    }
+
+    /// The entry to append to the history file for `command_script`.
+    fn get_history_line(&self, command_script: &str) -> String {
+        format!("{}\n", command_script)
+    }
+
+    /// Splits `TF_HISTORY`, as exported by this shell's function, into
+    /// commands, oldest first.
+    fn parse_tf_history(&self, output: &str) -> Vec<String> {
+        output.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Splits the contents of the history file into commands, oldest first.
+    fn parse_history(&self, contents: &str) -> Vec<String> {
+        contents.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Commands from the history file, oldest first. The file is read lossily
+    /// since shells don't guarantee it is valid UTF-8.
+    fn get_history(&self) -> Vec<String> {
+        match fs::read(self.get_history_file_name()) {
+            Ok(bytes) => self.parse_history(&String::from_utf8_lossy(&bytes)),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// A non-interactive invocation of this shell that runs `script`, used to
+    /// re-run the failed command and capture its output.
+    fn rerun_command(&self, script: &str) -> Command {
+        let mut cmd = Command::new(self.name());
+        cmd.arg("-c").arg(script);
+        cmd
+    }
+
+    /// Parses this shell's `alias` listing into name -> value.
+    fn parse_aliases(&self, _output: &str) -> HashMap<String, String> {
+        HashMap::new()
+    }
+
+    /// Aliases exported by the shell function in `TF_SHELL_ALIASES`.
+    fn get_aliases(&self) -> HashMap<String, String> {
+        env::var("TF_SHELL_ALIASES")
+            .map(|output| self.parse_aliases(&output))
+            .unwrap_or_default()
+    }
+
+    /// Shell functions exported by the shell function in `TF_SHELL_FUNCTIONS`,
+    /// one per line. Names starting with `_` are completion helpers and skipped.
+    fn get_functions(&self) -> Vec<String> {
+        env::var("TF_SHELL_FUNCTIONS")
+            .unwrap_or_default()
+            .lines()
+            .map(str::trim)
+            .filter(|name| !name.is_empty() && !name.starts_with('_'))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Quotes `s` as a single word.
+    fn quote(&self, s: &str) -> String {
+        shell_escape::unix::escape(s.into()).to_string()
+    }
+
+    /// Runs `second` only if `first` fails.
+    fn or(&self, first: &str, second: &str) -> String {
+        format!("{} || {}", first, second)
+    }
+
+    /// Replaces an alias in the first word of `script` with its value.
+    fn expand_aliases(&self, script: &str) -> String {
+        expand_alias(script, &self.get_aliases())
+    }
+}
+
+/// Appends `command_script` to the shell's history file in its own format.
+pub fn append_to_history(shell: &dyn Shell, command_script: &str) -> Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(shell.get_history_file_name())?;
+    file.write_all(shell.get_history_line(command_script).as_bytes())?;
+    Ok(())
+}
+
+/// Separates entries in `TF_HISTORY` for shells whose history listing can't
+/// tell a multi-line entry from several entries.
+pub const HISTORY_SEPARATOR: char = '\x1e';
+
+pub(crate) fn unix_timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+pub fn expand_alias(script: &str, aliases: &HashMap<String, String>) -> String {
+    let trimmed = script.trim_start();
+    let (first, rest) = trimmed.split_at(trimmed.find(char::is_whitespace).unwrap_or(trimmed.len()));
+
+    match aliases.get(first) {
+        Some(value) => format!("{}{}", value, rest),
+        None => script.to_string(),
+    }
+}
+
+/// Restarts an interactive bash or zsh session under `script(1)` so its
+/// output is logged to `TF_OUTPUT_LOG` for instant mode. Uses util-linux
+/// `script` options. The log is private to the user, preferably in the
+/// per-user runtime directory, and removed when the session ends.
+fn posix_output_log(shell: &str) -> String {
+    format!(
+        r#"
+if [ -z "$TF_OUTPUT_LOG" ] && [ -t 0 ] && command -v script >/dev/null 2>&1; then
+    if TF_OUTPUT_LOG=$(umask 077; mktemp "${{XDG_RUNTIME_DIR:-${{TMPDIR:-/tmp}}}}/ffs-XXXXXX"); then
+        export TF_OUTPUT_LOG;
+        script -qf -c {shell} "$TF_OUTPUT_LOG";
+        TF_STATUS=$?;
+        rm -f "$TF_OUTPUT_LOG";
+        exit $TF_STATUS;
+    fi
+    unset TF_OUTPUT_LOG;
+fi
+"#,
+        shell = shell
+    )
+}
+
+/// Parses `name=value` listings as printed by bash (`alias name='value'`) and
+/// zsh (`name=value`), where values use POSIX single quoting.
+fn parse_posix_aliases(output: &str) -> HashMap<String, String> {
+    output.lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            let line = line.strip_prefix("alias ").unwrap_or(line);
+            let word = shell_words::split(line).ok()?.into_iter().next()?;
+            let (name, value) = word.split_once('=')?;
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect()
 }
 
 pub mod bash;
 pub mod fish;
 pub mod zsh;
+pub mod powershell;
+pub mod tcsh;
+pub mod nushell;
 
 pub use self::bash::Bash;
 pub use self::fish::Fish;
 pub use self::zsh::Zsh;
+pub use self::powershell::PowerShell;
+pub use self::tcsh::Tcsh;
+pub use self::nushell::Nushell;
+
+/// Maps a shell or process name (`bash`, `-zsh`, `/usr/bin/fish`, `pwsh.exe`)
+/// to its implementation.
+pub fn shell_from_name(name: &str) -> Option<Box<dyn Shell>> {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    let name = name.trim_start_matches('-');
+    let name = name.strip_suffix(".exe").unwrap_or(name);
+
+    match name {
+        "bash" => Some(Box::new(Bash)),
+        "fish" => Some(Box::new(Fish)),
+        "zsh" => Some(Box::new(Zsh)),
+        "powershell" | "pwsh" => Some(Box::new(PowerShell)),
+        "tcsh" | "csh" => Some(Box::new(Tcsh)),
+        "nu" | "nushell" => Some(Box::new(Nushell)),
+        _ => None,
+    }
+}
+
+/// Finds the shell ffs is running under: `TF_SHELL` as exported by the alias,
+/// then the nearest shell among the parent processes, then the login shell in
+/// `$SHELL`. The process tree comes before `$SHELL` because `$SHELL` stays the
+/// login shell when the user starts another one.
+pub fn detect_shell() -> Option<Box<dyn Shell>> {
+    detect_shell_from(
+        env::var("TF_SHELL").ok(),
+        parent_process_names(),
+        env::var("SHELL").ok(),
+    )
+}
+
+/// The shell among the parent processes, falling back to `$SHELL`, without
+/// looking at `TF_SHELL`. For `--alias` and `setup`, which are run from the
+/// shell being configured; a `TF_SHELL` inherited from another shell's alias,
+/// e.g. bash started from fish, would name the wrong one.
+pub fn detect_running_shell() -> Option<Box<dyn Shell>> {
+    detect_shell_from(None, parent_process_names(), env::var("SHELL").ok())
+}
+
+pub fn detect_shell_from(
+    tf_shell: Option<String>,
+    process_names: impl IntoIterator<Item = String>,
+    login_shell: Option<String>,
+) -> Option<Box<dyn Shell>> {
+    tf_shell.as_deref().and_then(shell_from_name)
+        .or_else(|| process_names.into_iter().find_map(|n| shell_from_name(&n)))
+        .or_else(|| login_shell.as_deref().and_then(shell_from_name))
+}
+
+/// Names of the ancestors of this process, nearest first. Empty where `/proc`
+/// isn't available.
+fn parent_process_names() -> Vec<String> {
+    let mut names = Vec::new();
+    let mut pid = parent_pid("self");
+
+    // A few levels is enough to get past wrappers like `sudo` or `$(...)`
+    while let Some(p) = pid.filter(|&p| p > 1) {
+        if names.len() >= 8 {
+            break;
+        }
+        match fs::read_to_string(format!("/proc/{}/comm", p)) {
+            Ok(comm) => names.push(comm.trim().to_string()),
+            Err(_) => break,
+        }
+        pid = parent_pid(&p.to_string());
+    }
+
+    names
+}
+
+fn parent_pid(pid: &str) -> Option<u32> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    parse_ppid(&stat)
+}
+
+/// The ppid from `/proc/<pid>/stat`. The command name in parentheses may
+/// itself contain spaces or parentheses, so fields are counted from the last `)`.
+fn parse_ppid(stat: &str) -> Option<u32> {
+    let rest = &stat[stat.rfind(')')? + 1..];
+    rest.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Compares generated shell code with `src/shells/snapshots/<name>.snap`.
+/// Run the tests with `FFS_UPDATE_SNAPSHOTS=1` to rewrite the snapshots after
+/// an intended change.
+#[cfg(test)]
+pub(crate) fn assert_snapshot(name: &str, actual: &str) {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src/shells/snapshots")
+        .join(format!("{}.snap", name));
+
+    if env::var_os("FFS_UPDATE_SNAPSHOTS").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("missing snapshot {}, run with FFS_UPDATE_SNAPSHOTS=1", path.display()));
+    assert_eq!(actual, expected, "snapshot {} changed", name);
+}
+
+/// Options turning on everything the generated functions can do.
+#[cfg(test)]
+pub(crate) fn all_alias_options() -> AliasOptions {
+    AliasOptions { alter_history: true, yeah: true, repeat: true, instant: true }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alias_options_flags() {
+        assert_eq!(AliasOptions::default().flags(), "");
+        assert_eq!(all_alias_options().flags(), " --yeah --repeat --instant");
+    }
+
+    #[test]
+    fn test_is_valid_alias_name() {
+        assert!(is_valid_alias_name("fuck"));
+        assert!(is_valid_alias_name("_ffs-2"));
+        assert!(!is_valid_alias_name(""));
+        assert!(!is_valid_alias_name("-f"));
+        assert!(!is_valid_alias_name("a b"));
+        assert!(!is_valid_alias_name("x;rm"));
+    }
+
+    #[test]
+    fn test_shell_from_name() {
+        assert_eq!(shell_from_name("bash").unwrap().name(), "bash");
+        assert_eq!(shell_from_name("-zsh").unwrap().name(), "zsh");
+        assert_eq!(shell_from_name("/usr/local/bin/fish").unwrap().name(), "fish");
+        assert_eq!(shell_from_name("pwsh.exe").unwrap().name(), "powershell");
+        assert!(shell_from_name("cargo").is_none());
+    }
+
+    #[test]
+    fn test_detect_shell_order() {
+        let procs = || vec!["sudo".to_string(), "zsh".to_string()];
+
+        let shell = detect_shell_from(Some("fish".to_string()), procs(), Some("/bin/bash".to_string()));
+        assert_eq!(shell.unwrap().name(), "fish");
+
+        let shell = detect_shell_from(None, procs(), Some("/bin/bash".to_string()));
+        assert_eq!(shell.unwrap().name(), "zsh");
+
+        let shell = detect_shell_from(None, vec![], Some("/bin/bash".to_string()));
+        assert_eq!(shell.unwrap().name(), "bash");
+
+        assert!(detect_shell_from(Some("cmd".to_string()), vec![], None).is_none());
+    }
+
+    #[test]
+    fn test_expand_alias() {
+        let aliases = HashMap::from([("g".to_string(), "git".to_string())]);
+        assert_eq!(expand_alias("g psuh origin", &aliases), "git psuh origin");
+        assert_eq!(expand_alias("g", &aliases), "git");
+        assert_eq!(expand_alias("gg psuh", &aliases), "gg psuh");
+    }
+
+    #[test]
+    fn test_keybindings_skip_history() {
+        for name in ["bash", "zsh", "fish", "tcsh", "powershell", "nu"] {
+            if let Some(keybinding) = shell_from_name(name).unwrap().keybinding("ffs") {
+                assert!(keybinding.contains("command ffs --no-history "), "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_ppid() {
+        assert_eq!(parse_ppid("1234 (my (weird) cmd) S 42 1234 1234 0"), Some(42));
+        assert_eq!(parse_ppid("garbage"), None);
+    }
+}
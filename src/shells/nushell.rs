@@ -0,0 +1,110 @@
+use super::{AliasOptions, Shell};
+use std::collections::HashMap;
+
+pub struct Nushell;
+
+impl Shell for Nushell {
+    fn name(&self) -> &'static str {
+        "nu"
+    }
+
+    fn app_alias(&self, alias_name: &str, options: &AliasOptions) -> String {
+        // `history | last 2` is the previous command followed by this call.
+        // Nushell has no `eval`, so the correction is handed back to the line
+        // editor and accepted, which runs it in the current scope and records it
+        // in history like a typed command, so there's no history to alter.
+        format!(
+            r#"
+def --env {alias_name} [...args] {{
+    $env.TF_EXIT_CODE = ($env.LAST_EXIT_CODE | into string)
+    $env.TF_SHELL = "nu"
+    $env.TF_ALIAS = "{alias_name}"
+    $env.TF_HISTORY = (history | last 2 | get command | first)
+    $env.TF_SHELL_ALIASES = (scope aliases | each {{|a| $"($a.name)\t($a.expansion)" }} | str join "\n")
+    let tf_cmd = (^ffs{flags} ...$args | str trim)
+    hide-env TF_HISTORY TF_EXIT_CODE TF_SHELL_ALIASES TF_SHELL TF_ALIAS
+    if ($tf_cmd | is-not-empty) {{
+        commandline edit --replace --accept $tf_cmd
+    }}
+}}
+"#,
+            alias_name = alias_name,
+            flags = options.flags()
+        )
+    }
+
+    fn quote(&self, s: &str) -> String {
+        // A raw string ends at `'` followed by as many `#` as opened it
+        let mut hashes = String::from("#");
+        while s.contains(&format!("'{}", hashes)) {
+            hashes.push('#');
+        }
+        format!("r{0}'{1}'{0}", hashes, s)
+    }
+
+    fn or(&self, first: &str, second: &str) -> String {
+        format!("{}; if $env.LAST_EXIT_CODE != 0 {{ {} }}", first, second)
+    }
+
+    fn get_history_file_name(&self) -> String {
+        dirs::config_dir()
+            .map(|d| d.join("nushell").join("history.txt").to_string_lossy().to_string())
+            .unwrap_or_else(|| "history.txt".to_string())
+    }
+
+    fn parse_tf_history(&self, output: &str) -> Vec<String> {
+        // The function exports just the previous command, which may span lines
+        let entry = output.trim();
+        if entry.is_empty() { vec![] } else { vec![entry.to_string()] }
+    }
+
+    fn parse_aliases(&self, output: &str) -> HashMap<String, String> {
+        // The function exports `name<TAB>expansion` per alias
+        output.lines()
+            .filter_map(|line| line.split_once('\t'))
+            .map(|(name, value)| (name.to_string(), value.trim().to_string()))
+            .collect()
+    }
+
+    fn parse_history(&self, contents: &str) -> Vec<String> {
+        contents.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.replace("<\\n>", "\n"))
+            .collect()
+    }
+
+    fn get_history_line(&self, command_script: &str) -> String {
+        // Plaintext history keeps one entry per line, escaping embedded newlines
+        format!("{}\n", command_script.replace('\n', "<\\n>"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shells::{all_alias_options, assert_snapshot};
+
+    #[test]
+    fn test_history_line_escapes_newlines() {
+        assert_eq!(Nushell.get_history_line("echo a\necho b"), "echo a<\\n>echo b\n");
+    }
+
+    #[test]
+    fn test_snapshots() {
+        assert_snapshot("nushell_alias", &Nushell.app_alias("ffs", &AliasOptions::default()));
+        assert_snapshot("nushell_alias_all_options", &Nushell.app_alias("fuck", &all_alias_options()));
+    }
+
+    #[test]
+    fn test_parse_aliases() {
+        let aliases = Nushell.parse_aliases("g\tgit\nll\tls -l\n");
+        assert_eq!(aliases.len(), 2);
+        assert_eq!(aliases["ll"], "ls -l");
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(Nushell.quote("echo 'a'"), "r#'echo 'a''#");
+        assert_eq!(Nushell.quote("echo 'a'#"), "r##'echo 'a'#'##");
+    }
+}
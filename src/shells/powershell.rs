@@ -0,0 +1,169 @@
+use super::{AliasOptions, HISTORY_SEPARATOR, Shell, ShellConfiguration};
+use std::collections::HashMap;
+use std::process::Command;
+
+pub struct PowerShell;
+
+impl Shell for PowerShell {
+    fn name(&self) -> &'static str {
+        "powershell"
+    }
+
+    fn app_alias(&self, alias_name: &str, options: &AliasOptions) -> String {
+        let history = if options.alter_history {
+            "\n        [Microsoft.PowerShell.PSConsoleReadLine]::AddToHistory($TF_CMD);"
+        } else {
+            ""
+        };
+        format!(
+            r#"
+function {alias_name} {{
+    $env:TF_EXIT_CODE = $LASTEXITCODE;
+    $env:TF_SHELL = "powershell";
+    $env:TF_ALIAS = "{alias_name}";
+    $env:TF_HISTORY = (Get-History -Count 10 | ForEach-Object {{ $_.CommandLine }}) -join [char]0x1e;
+    $env:TF_SHELL_ALIASES = (Get-Alias | ForEach-Object {{ "$($_.Name)`t$($_.Definition)" }}) -join "`n";
+    $TF_BIN = Get-Command ffs -CommandType Application | Select-Object -First 1;
+    $TF_CMD = (& $TF_BIN{flags} @args) -join "`n";
+    $BLOCK_RES = $LASTEXITCODE;
+    Remove-Item Env:TF_HISTORY, Env:TF_EXIT_CODE, Env:TF_SHELL_ALIASES, Env:TF_SHELL, Env:TF_ALIAS -ErrorAction Ignore;
+    if ($BLOCK_RES -eq 0 -and -not [string]::IsNullOrWhiteSpace($TF_CMD)) {{
+        Invoke-Expression $TF_CMD;{history}
+    }}
+    [Console]::ResetColor();
+}}
+"#,
+            alias_name = alias_name,
+            flags = options.flags(),
+            history = history
+        )
+    }
+
+    fn quote(&self, s: &str) -> String {
+        format!("'{}'", s.replace('\'', "''"))
+    }
+
+    fn or(&self, first: &str, second: &str) -> String {
+        // `||` only exists from PowerShell 7
+        format!("{}; if (-not $?) {{ {} }}", first, second)
+    }
+
+    fn how_to_configure(&self) -> Option<ShellConfiguration> {
+        // `$PROFILE` for the current user and host
+        let dir = if cfg!(windows) {
+            dirs::document_dir()?.join("PowerShell")
+        } else {
+            dirs::config_dir()?.join("powershell")
+        };
+        Some(ShellConfiguration {
+            content: "Invoke-Expression (ffs --alias powershell | Out-String)".to_string(),
+            path: dir.join("Microsoft.PowerShell_profile.ps1"),
+        })
+    }
+
+    fn get_history_file_name(&self) -> String {
+        // PSReadLine keeps one history file per host, under %APPDATA% on
+        // Windows and the XDG data dir elsewhere.
+        let base = if cfg!(windows) {
+            dirs::config_dir().map(|d| d.join("Microsoft").join("Windows").join("PowerShell"))
+        } else {
+            dirs::data_dir().map(|d| d.join("powershell"))
+        };
+
+        base.map(|b| b.join("PSReadLine").join("ConsoleHost_history.txt").to_string_lossy().to_string())
+            .unwrap_or_else(|| "ConsoleHost_history.txt".to_string())
+    }
+
+    fn parse_tf_history(&self, output: &str) -> Vec<String> {
+        output.split(HISTORY_SEPARATOR)
+            .map(|entry| entry.trim().to_string())
+            .filter(|entry| !entry.is_empty())
+            .collect()
+    }
+
+    fn parse_aliases(&self, output: &str) -> HashMap<String, String> {
+        // The function exports `name<TAB>definition` per alias
+        output.lines()
+            .filter_map(|line| line.split_once('\t'))
+            .map(|(name, value)| (name.to_string(), value.trim().to_string()))
+            .collect()
+    }
+
+    fn parse_history(&self, contents: &str) -> Vec<String> {
+        // PSReadLine ends each line of a multi-line entry but the last with a backtick
+        let mut entries = Vec::new();
+        let mut current = String::new();
+        for line in contents.lines() {
+            match line.strip_suffix('`') {
+                Some(part) => {
+                    current.push_str(part);
+                    current.push('\n');
+                }
+                None => {
+                    current.push_str(line);
+                    if !current.trim().is_empty() {
+                        entries.push(std::mem::take(&mut current));
+                    }
+                    current.clear();
+                }
+            }
+        }
+        entries
+    }
+
+    fn rerun_command(&self, script: &str) -> Command {
+        // Windows PowerShell is `powershell.exe`; PowerShell 7 and every non-Windows
+        // install is `pwsh`.
+        let exe = if cfg!(windows) && which::which("pwsh").is_err() { "powershell" } else { "pwsh" };
+        let mut cmd = Command::new(exe);
+        cmd.args(["-NoProfile", "-NonInteractive", "-Command", script]);
+        cmd
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shells::{all_alias_options, assert_snapshot};
+
+    #[test]
+    fn test_history_file_name() {
+        assert!(PowerShell.get_history_file_name().ends_with("ConsoleHost_history.txt"));
+    }
+
+    #[test]
+    fn test_app_alias_parses_in_pwsh() {
+        // Only runs where PowerShell is installed (`pwsh` on Linux and macOS)
+        let pwsh = match which::which("pwsh") {
+            Ok(p) => p,
+            Err(_) => return,
+        };
+
+        let script = format!("{}\n(Get-Command fuck).CommandType", PowerShell.app_alias("fuck", &AliasOptions::default()));
+        let output = Command::new(pwsh)
+            .args(["-NoProfile", "-NonInteractive", "-Command", &script])
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "Function");
+    }
+
+    #[test]
+    fn test_snapshots() {
+        assert_snapshot("powershell_alias", &PowerShell.app_alias("ffs", &AliasOptions::default()));
+        assert_snapshot("powershell_alias_all_options", &PowerShell.app_alias("fuck", &all_alias_options()));
+    }
+
+    #[test]
+    fn test_parse_aliases() {
+        let aliases = PowerShell.parse_aliases("gci\tGet-ChildItem\nll\tGet-ChildItem\n");
+        assert_eq!(aliases.len(), 2);
+        assert_eq!(aliases["gci"], "Get-ChildItem");
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(PowerShell.quote("echo 'a' $b"), "'echo ''a'' $b'");
+    }
+}
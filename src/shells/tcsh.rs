@@ -0,0 +1,106 @@
+use super::{AliasOptions, HISTORY_SEPARATOR, Shell, ShellConfiguration, unix_timestamp};
+use std::collections::HashMap;
+
+pub struct Tcsh;
+
+impl Shell for Tcsh {
+    fn name(&self) -> &'static str {
+        "tcsh"
+    }
+
+    fn app_alias(&self, alias_name: &str, options: &AliasOptions) -> String {
+        // tcsh aliases are a single line. Inside the alias `history -h 2` lists the
+        // previous command followed by the alias call itself, and `\!*` is the
+        // alias arguments. `$status` is read first, while it is still the failed
+        // command's. `\ffs` skips alias expansion so an alias named `ffs`
+        // doesn't call itself, and the double-quoted backticks keep the correction
+        // as one word so `eval` sees it exactly as ffs printed it. With
+        // alter_history, ffs appends the correction to the history file and
+        // `history -M` merges it back in. Backquotes split on newlines even in
+        // double quotes, so the `alias` listing is joined with `\036`; `tr`'s
+        // arguments are backslash-escaped as the alias is already single-quoted.
+        let history = if options.alter_history { "; history -M" } else { "" };
+        format!(
+            "alias {alias_name} 'setenv TF_EXIT_CODE $status; setenv TF_SHELL tcsh; setenv TF_ALIAS {alias_name}; \
+setenv TF_HISTORY \"`history -h 2 | head -n 1`\"; \
+setenv TF_SHELL_ALIASES \"`alias | tr \\\\n \\\\036`\"; \
+set TF_CMD=\"`\\ffs{flags} \\!*`\"; unsetenv TF_HISTORY TF_EXIT_CODE TF_SHELL_ALIASES TF_SHELL TF_ALIAS; \
+if (\"$TF_CMD\" != \"\") eval \"$TF_CMD\"{history}'",
+            alias_name = alias_name,
+            flags = options.flags(),
+            history = history
+        )
+    }
+
+    fn how_to_configure(&self) -> Option<ShellConfiguration> {
+        Some(ShellConfiguration {
+            content: "eval `ffs --alias tcsh`".to_string(),
+            path: dirs::home_dir()?.join(".tcshrc"),
+        })
+    }
+
+    fn get_history_file_name(&self) -> String {
+        std::env::var("HISTFILE").unwrap_or_else(|_| {
+            dirs::home_dir()
+                .map(|h| h.join(".history").to_string_lossy().to_string())
+                .unwrap_or_else(|| ".history".to_string())
+        })
+    }
+
+    fn parse_history(&self, contents: &str) -> Vec<String> {
+        contents.lines()
+            .filter(|line| !line.trim().is_empty() && !line.starts_with("#+"))
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn writes_history_file(&self) -> bool {
+        true
+    }
+
+    fn parse_aliases(&self, output: &str) -> HashMap<String, String> {
+        // `alias` prints `name<TAB>value`, with multi-word values in parentheses
+        output.split(['\n', HISTORY_SEPARATOR])
+            .filter_map(|line| line.split_once('\t'))
+            .map(|(name, value)| {
+                let value = value.strip_prefix('(').and_then(|v| v.strip_suffix(')')).unwrap_or(value);
+                (name.to_string(), value.to_string())
+            })
+            .collect()
+    }
+
+    fn get_history_line(&self, command_script: &str) -> String {
+        format!("#+{}\n{}\n", unix_timestamp(), command_script)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shells::{all_alias_options, assert_snapshot};
+
+    #[test]
+    fn test_history_line() {
+        let line = Tcsh.get_history_line("ls -la");
+        assert!(line.starts_with("#+"));
+        assert!(line.ends_with("\nls -la\n"));
+    }
+
+    #[test]
+    fn test_parse_aliases() {
+        let aliases = Tcsh.parse_aliases("g\tgit\nll\t(ls -l)\n");
+        assert_eq!(aliases["g"], "git");
+        assert_eq!(aliases["ll"], "ls -l");
+
+        // As exported by the alias
+        let aliases = Tcsh.parse_aliases("g\tgit\x1ell\t(ls -l)\x1e");
+        assert_eq!(aliases.len(), 2);
+        assert_eq!(aliases["ll"], "ls -l");
+    }
+
+    #[test]
+    fn test_snapshots() {
+        assert_snapshot("tcsh_alias", &Tcsh.app_alias("ffs", &AliasOptions::default()));
+        assert_snapshot("tcsh_alias_all_options", &Tcsh.app_alias("fuck", &all_alias_options()));
+    }
+}
@@ -1,36 +1,68 @@
-use super::Shell;
+use super::{AliasOptions, HISTORY_SEPARATOR, Shell, ShellConfiguration, parse_posix_aliases, posix_output_log};
+use std::collections::HashMap;
 
 pub struct Zsh;
 
 impl Shell for Zsh {
-    fn app_alias(&self, alias_name: &str) -> String {
+    fn name(&self) -> &'static str {
+        "zsh"
+    }
+
+    fn app_alias(&self, alias_name: &str, options: &AliasOptions) -> String {
+        // `print -s` adds the correction to the session history, which zsh
+        // saves to $HISTFILE itself, so ffs never writes the file
+        let history = if options.alter_history { "\n        print -sr -- \"$TF_CMD\";" } else { "" };
+        let output_log = if options.instant { posix_output_log("zsh") } else { String::new() };
         format!(
-            r#"
+            r#"{output_log}
 function {alias_name} () {{
-    TF_PYTHONIOENCODING=$PYTHONIOENCODING;
-    export TF_SHELL=zsh;
-    export TF_ALIAS={alias_name};
-    export TF_SHELL_ALIASES=$(alias);
-    export TF_HISTORY="$(fc -ln -10)";
-    export PYTHONIOENCODING=utf-8;
+    local TF_EXIT_CODE=$? TF_CMD;
     TF_CMD=$(
-        ffs "$@"
-    );
-    BLOCK_RES=$?;
-    if [ $BLOCK_RES -eq 0 ]; then
+        TF_SHELL=zsh TF_ALIAS={alias_name} TF_EXIT_CODE=$TF_EXIT_CODE \
+        TF_SHELL_ALIASES="$(alias)" TF_SHELL_FUNCTIONS="$(print -l ${{(k)functions}})" \
+        TF_HISTORY="$(for i in {{$((HISTCMD - 9))..$HISTCMD}}; do print -rn -- "${{history[$i]}}"$'\x1e'; done)" \
+        command ffs{flags} "$@"
+    ) || return;
+    if [ -n "$TF_CMD" ]; then{history}
         eval "$TF_CMD";
-    else
-        return $BLOCK_RES;
     fi
-    unset TF_HISTORY;
-    export PYTHONIOENCODING=$TF_PYTHONIOENCODING;
-    print -s $TF_CMD;
 }}
 "#,
-            alias_name = alias_name
+            output_log = output_log,
+            alias_name = alias_name,
+            flags = options.flags(),
+            history = history
         )
     }
 
+    fn keybinding(&self, alias_name: &str) -> Option<String> {
+        // Widgets run with stdin closed, so the selection menu reads /dev/tty
+        Some(format!(
+            r#"
+ffs-fix-buffer () {{
+    local TF_CMD;
+    TF_CMD=$(
+        TF_SHELL=zsh TF_ALIAS={alias_name} TF_SHELL_ALIASES="$(alias)" \
+        TF_SHELL_FUNCTIONS="$(print -l ${{(k)functions}})" TF_HISTORY="${{history[$((HISTCMD - 1))]}}" command ffs --no-history </dev/tty
+    );
+    if [[ -n "$TF_CMD" ]]; then
+        BUFFER=$TF_CMD;
+        CURSOR=${{#BUFFER}};
+    fi
+    zle reset-prompt;
+}}
+zle -N ffs-fix-buffer;
+bindkey '\e\e' ffs-fix-buffer;
+"#,
+            alias_name = alias_name
+        ))
+    }
+
+    fn how_to_configure(&self) -> Option<ShellConfiguration> {
+        let dir = std::env::var_os("ZDOTDIR").map(std::path::PathBuf::from).or_else(dirs::home_dir)?;
+        Some(ShellConfiguration { content: r#"eval "$(ffs --alias zsh)""#.to_string(), path: dir.join(".zshrc") })
+    }
+
     fn get_history_file_name(&self) -> String {
         std::env::var("HISTFILE").unwrap_or_else(|_| {
             dirs::home_dir()
@@ -38,4 +70,112 @@ function {alias_name} () {{
                 .unwrap_or_else(|| ".zsh_history".to_string())
         })
     }
+
+    fn parse_tf_history(&self, output: &str) -> Vec<String> {
+        // The function joins raw `$history` entries with the separator, oldest
+        // first. `fc -l` output from older setups has one entry per line,
+        // `  <number>  command` unless `-n` is given; its `\n` for newlines
+        // can't be told apart from a typed `\n`, so it's left alone.
+        if output.contains(HISTORY_SEPARATOR) {
+            return output.split(HISTORY_SEPARATOR)
+                .map(|entry| entry.trim().to_string())
+                .filter(|entry| !entry.is_empty())
+                .collect();
+        }
+        output.lines()
+            .map(|line| strip_event_number(line).trim().to_string())
+            .filter(|entry| !entry.is_empty())
+            .collect()
+    }
+
+    fn parse_history(&self, contents: &str) -> Vec<String> {
+        let mut entries = Vec::new();
+        let mut current: Option<String> = None;
+
+        for line in contents.lines() {
+            let entry = match current.take() {
+                // Continuation of a multi-line command
+                Some(mut entry) => {
+                    entry.push('\n');
+                    entry.push_str(line);
+                    entry
+                }
+                None => strip_extended_prefix(line).to_string(),
+            };
+
+            match entry.strip_suffix('\\') {
+                Some(stripped) => current = Some(stripped.to_string()),
+                None if !entry.trim().is_empty() => entries.push(entry),
+                None => {}
+            }
+        }
+        entries.extend(current);
+        entries
+    }
+
+    fn parse_aliases(&self, output: &str) -> HashMap<String, String> {
+        parse_posix_aliases(output)
+    }
+}
+
+fn strip_event_number(line: &str) -> &str {
+    let trimmed = line.trim_start();
+    let number_len = trimmed.find(|c: char| !c.is_ascii_digit()).unwrap_or(trimmed.len());
+    if number_len == 0 {
+        return line;
+    }
+    // The number is followed by `*` for edited entries or a space, then a space
+    let rest = &trimmed[number_len..];
+    rest.strip_prefix("* ").or_else(|| rest.strip_prefix("  ")).unwrap_or(line)
+}
+
+/// Strips the `: <start>:<duration>;` prefix of extended history entries.
+fn strip_extended_prefix(line: &str) -> &str {
+    let Some(rest) = line.strip_prefix(": ") else { return line };
+    match rest.split_once(';') {
+        Some((meta, command)) if meta.chars().all(|c| c.is_ascii_digit() || c == ':') => command,
+        _ => line,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shells::{all_alias_options, assert_snapshot};
+
+    #[test]
+    fn test_parse_tf_history() {
+        let output = "ls\x1efor i in 1 2; do\n  echo $i\ndone\x1e\x1eprintf 'a\\nb'\x1e7z x archive.7z\x1e";
+        assert_eq!(Zsh.parse_tf_history(output), vec!["ls", "for i in 1 2; do\n  echo $i\ndone", "printf 'a\\nb'", "7z x archive.7z"]);
+
+        // `fc -ln -10`, with a literal `\n` kept as typed
+        let output = "ls\necho \"x\\n\"\n";
+        assert_eq!(Zsh.parse_tf_history(output), vec!["ls", "echo \"x\\n\""]);
+
+        // `fc -l -10`
+        let output = "   41  git psuh\n   42* ls -la\n";
+        assert_eq!(Zsh.parse_tf_history(output), vec!["git psuh", "ls -la"]);
+    }
+
+    #[test]
+    fn test_parse_history() {
+        let contents = ": 1700000000:0;ls\n: 1700000001:2;for f in *; do\\\necho $f\\\ndone\nplain entry\n: 1700000005:0;git psuh\n";
+        let history = Zsh.parse_history(contents);
+        assert_eq!(history, vec!["ls", "for f in *; do\necho $f\ndone", "plain entry", "git psuh"]);
+    }
+
+    #[test]
+    fn test_parse_aliases() {
+        let aliases = Zsh.parse_aliases("g=git\nll='ls -l'\nrun-help=man\n");
+        assert_eq!(aliases.len(), 3);
+        assert_eq!(aliases["g"], "git");
+        assert_eq!(aliases["ll"], "ls -l");
+    }
+
+    #[test]
+    fn test_snapshots() {
+        assert_snapshot("zsh_alias", &Zsh.app_alias("ffs", &AliasOptions::default()));
+        assert_snapshot("zsh_alias_all_options", &Zsh.app_alias("fuck", &all_alias_options()));
+        assert_snapshot("zsh_keybinding", &Zsh.keybinding("ffs").unwrap());
+    }
 }
@@ -0,0 +1,206 @@
+use crate::types::Command;
+use crate::rules::Rule;
+use crate::scripting::RhaiRule;
+use anyhow::{Result, anyhow};
+use colored::*;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const TEST_SUFFIX: &str = ".test.toml";
+
+/// One `[[test]]` entry in a `<rule>.test.toml` file. An empty `expected`
+/// means the rule must not match.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TestCase {
+    pub name: Option<String>,
+    pub script: String,
+    #[serde(default)]
+    pub stdout: String,
+    #[serde(default)]
+    pub stderr: String,
+    pub exit_code: Option<i32>,
+    #[serde(default)]
+    pub expected: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TestFile {
+    #[serde(default)]
+    test: Vec<TestCase>,
+}
+
+#[derive(Debug)]
+pub struct TestResult {
+    pub rule: String,
+    pub case: String,
+    pub expected: Vec<String>,
+    pub actual: Vec<String>,
+    pub error: Option<String>,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.expected == self.actual
+    }
+}
+
+/// `*.test.toml` files directly inside `dirs`, sorted by path.
+pub fn discover_test_files(dirs: &[PathBuf]) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = dirs.iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flat_map(|entries| entries.flatten().map(|e| e.path()))
+        .filter(|p| is_test_file(p))
+        .collect();
+    files.sort();
+    files
+}
+
+pub fn is_test_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.ends_with(TEST_SUFFIX))
+}
+
+/// Runs every case in `test_file` against the `.rhai` rule next to it.
+pub fn run_test_file(test_file: &Path) -> Vec<TestResult> {
+    let file_name = test_file.file_name().unwrap_or_default().to_string_lossy();
+    let rule_name = file_name.trim_end_matches(TEST_SUFFIX).to_string();
+    let rule_path = test_file.with_file_name(format!("{}.rhai", rule_name));
+
+    let failure = |error: anyhow::Error| vec![TestResult {
+        rule: rule_name.clone(),
+        case: test_file.display().to_string(),
+        expected: vec![],
+        actual: vec![],
+        error: Some(error.to_string()),
+    }];
+
+    let cases = match load_cases(test_file) {
+        Ok(cases) => cases,
+        Err(e) => return failure(e),
+    };
+    let rule = match load_rule(&rule_name, &rule_path) {
+        Ok(rule) => rule,
+        Err(e) => return failure(e),
+    };
+
+    cases.into_iter()
+        .enumerate()
+        .map(|(i, case)| {
+            let command = Command::from_output(case.script.clone(), case.stdout, case.stderr)
+                .with_exit_code(case.exit_code);
+            let actual = if rule.matches(&command) {
+                rule.generate_corrections(&command).into_iter().map(|c| c.command).collect()
+            } else {
+                vec![]
+            };
+
+            TestResult {
+                rule: rule_name.clone(),
+                case: case.name.unwrap_or_else(|| format!("#{} {}", i + 1, case.script)),
+                expected: case.expected,
+                actual,
+                error: None,
+            }
+        })
+        .collect()
+}
+
+fn load_cases(test_file: &Path) -> Result<Vec<TestCase>> {
+    let contents = fs::read_to_string(test_file)?;
+    let file: TestFile = toml::from_str(&contents)?;
+    Ok(file.test)
+}
+
+fn load_rule(name: &str, path: &Path) -> Result<RhaiRule> {
+    let script = fs::read_to_string(path)
+        .map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+    RhaiRule::new(name.to_string(), &script, 100)
+}
+
+/// Prints one line per case, with an expected/actual diff for failures.
+/// Returns whether every case passed.
+pub fn report(results: &[TestResult]) -> bool {
+    for result in results {
+        if result.passed() {
+            println!("{} {} {}", "ok".green(), result.rule, result.case);
+            continue;
+        }
+
+        println!("{} {} {}", "FAIL".red().bold(), result.rule, result.case);
+        if let Some(error) = &result.error {
+            println!("    {}", error);
+            continue;
+        }
+        for line in &result.expected {
+            if !result.actual.contains(line) {
+                println!("    {}", format!("- {}", line).red());
+            }
+        }
+        for line in &result.actual {
+            if !result.expected.contains(line) {
+                println!("    {}", format!("+ {}", line).green());
+            }
+        }
+        if result.expected.is_empty() || result.actual.is_empty() {
+            println!("    expected {} correction(s), got {}", result.expected.len(), result.actual.len());
+        } else if result.expected.iter().all(|e| result.actual.contains(e)) {
+            println!("    corrections are out of order: {:?}", result.actual);
+        }
+    }
+
+    let failed = results.iter().filter(|r| !r.passed()).count();
+    println!("\n{} passed, {} failed", results.len() - failed, failed);
+    failed == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_run_test_file() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("gti.rhai"), r#"
+            fn matches() { script.starts_with("gti ") }
+            fn get_new_command() { "git" + script.sub_string(3) }
+        "#).unwrap();
+        fs::write(dir.path().join("gti.test.toml"), r#"
+            [[test]]
+            name = "status"
+            script = "gti status"
+            expected = ["git status"]
+
+            [[test]]
+            script = "git status"
+
+            [[test]]
+            script = "gti log"
+            expected = ["git lg"]
+        "#).unwrap();
+
+        let files = discover_test_files(&[dir.path().to_path_buf()]);
+        assert_eq!(files, vec![dir.path().join("gti.test.toml")]);
+
+        let results = run_test_file(&files[0]);
+        assert_eq!(results.len(), 3);
+        assert!(results[0].passed());
+        assert_eq!(results[0].case, "status");
+        assert!(results[1].passed());
+        assert!(!results[2].passed());
+        assert_eq!(results[2].actual, vec!["git log".to_string()]);
+    }
+
+    #[test]
+    fn test_missing_rule() {
+        let dir = tempdir().unwrap();
+        let test_file = dir.path().join("nothing.test.toml");
+        fs::write(&test_file, "[[test]]\nscript = \"x\"\n").unwrap();
+
+        let results = run_test_file(&test_file);
+        assert_eq!(results.len(), 1);
+        assert!(results[0].error.is_some());
+    }
+}
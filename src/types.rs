@@ -1,20 +1,47 @@
-use std::path::PathBuf;
+use crate::output::{normalize, truncate, MAX_OUTPUT};
 
 #[derive(Debug, Clone)]
 pub struct Command {
     pub script: String,
     pub stdout: String,
     pub stderr: String,
+    /// Output as the command printed it, before [`normalize`], cut to the same
+    /// [`MAX_OUTPUT`] bytes.
+    pub raw_stdout: String,
+    pub raw_stderr: String,
+    pub exit_code: Option<i32>,
 }
 
 impl Command {
+    /// A command whose output is used as given.
     pub fn new(script: String, stdout: String, stderr: String) -> Self {
         Self {
             script,
+            raw_stdout: stdout.clone(),
+            raw_stderr: stderr.clone(),
             stdout,
             stderr,
+            exit_code: None,
         }
     }
+
+    /// A command that printed `stdout` and `stderr`, normalized for matching
+    /// and kept as-is, apart from truncation, in `raw_stdout` and `raw_stderr`.
+    pub fn from_output(script: String, stdout: String, stderr: String) -> Self {
+        Self {
+            script,
+            stdout: normalize(&stdout),
+            stderr: normalize(&stderr),
+            raw_stdout: truncate(&stdout, MAX_OUTPUT),
+            raw_stderr: truncate(&stderr, MAX_OUTPUT),
+            exit_code: None,
+        }
+    }
+
+    pub fn with_exit_code(mut self, exit_code: Option<i32>) -> Self {
+        self.exit_code = exit_code;
+        self
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,247 @@
+//! Scoring of how likely a typed command is a typo of a known one.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+pub const DEFAULT_MAX_DISTANCE: f64 = 3.0;
+pub const DEFAULT_MIN_SIMILARITY: f64 = 0.5;
+
+/// Cost of swapping two neighbouring characters, the most common typo.
+const TRANSPOSITION_COST: f64 = 0.5;
+/// Cost of hitting a key next to the right one.
+const ADJACENT_KEY_COST: f64 = 0.5;
+/// Bonus per leading character in common, up to `PREFIX_LEN` characters;
+/// people rarely get the first letters of a command wrong.
+const PREFIX_BONUS: f64 = 0.05;
+const PREFIX_LEN: usize = 3;
+/// Bonus per natural-log unit of how often the command appears in history.
+const FREQUENCY_BONUS: f64 = 0.05;
+
+/// Scores `candidate` as a correction of `typed`, higher being better, or
+/// `None` if it's too different to suggest.
+pub trait TypoScorer: Send + Sync + Debug {
+    fn score(&self, typed: &str, candidate: &str) -> Option<f64>;
+
+    /// Damerau-Levenshtein distance from `typed` within which candidates are
+    /// worth scoring, used to narrow down the executable index before calling
+    /// `score`. Must cover every candidate `score` accepts.
+    fn search_radius(&self, typed: &str) -> usize;
+}
+
+/// Combines a keyboard-aware Damerau-Levenshtein distance with bonuses for a
+/// shared prefix and for commands the user runs often.
+#[derive(Debug, Clone)]
+pub struct DefaultScorer {
+    max_distance: f64,
+    min_similarity: f64,
+    frequencies: HashMap<String, usize>,
+}
+
+impl Default for DefaultScorer {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_DISTANCE, DEFAULT_MIN_SIMILARITY)
+    }
+}
+
+impl DefaultScorer {
+    pub fn new(max_distance: f64, min_similarity: f64) -> Self {
+        Self { max_distance, min_similarity, frequencies: HashMap::new() }
+    }
+
+    /// Counts of how often each command was run, e.g. from shell history.
+    pub fn with_frequencies(mut self, frequencies: HashMap<String, usize>) -> Self {
+        self.frequencies = frequencies;
+        self
+    }
+}
+
+impl TypoScorer for DefaultScorer {
+    fn score(&self, typed: &str, candidate: &str) -> Option<f64> {
+        if typed == candidate {
+            return None;
+        }
+        let distance = typo_distance(typed, candidate);
+        let len = typed.chars().count().max(candidate.chars().count()) as f64;
+        let similarity = 1.0 - distance / len;
+        if distance > self.max_distance || similarity <= self.min_similarity {
+            return None;
+        }
+
+        let prefix = typed.chars().zip(candidate.chars()).take(PREFIX_LEN).take_while(|(a, b)| a == b).count();
+        let frequency = self.frequencies.get(candidate).map_or(0.0, |&n| (n as f64).ln_1p());
+        Some(similarity + prefix as f64 * PREFIX_BONUS + frequency * FREQUENCY_BONUS)
+    }
+
+    fn search_radius(&self, typed: &str) -> usize {
+        // Every edit counts as one in the index's distance and costs at least
+        // 0.5 here, a transposition or a neighbouring key
+        let by_distance = (self.max_distance / TRANSPOSITION_COST.min(ADJACENT_KEY_COST)).ceil() as usize;
+        // `score` wants `distance < len * (1 - min_similarity)`, so short words
+        // only get a few edits; the bound is strict, hence one less than the ceiling
+        let len = typed.chars().count() as f64;
+        let bound = len * (1.0 - self.min_similarity) / TRANSPOSITION_COST.min(ADJACENT_KEY_COST);
+        let by_similarity = (bound.ceil() as usize).saturating_sub(1);
+        by_distance.min(by_similarity)
+    }
+}
+
+/// How often each command starts an entry in `history`.
+pub fn command_frequencies<'a>(history: impl IntoIterator<Item = &'a String>) -> HashMap<String, usize> {
+    let mut frequencies = HashMap::new();
+    for entry in history {
+        if let Some(program) = entry.split_whitespace().next() {
+            *frequencies.entry(program.to_string()).or_insert(0) += 1;
+        }
+    }
+    frequencies
+}
+
+/// Optimal string alignment distance where transpositions and substitutions
+/// of neighbouring keys cost less than other edits.
+pub fn typo_distance(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0.0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i as f64;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j as f64;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = if a[i - 1] == b[j - 1] {
+                0.0
+            } else if keys_adjacent(a[i - 1], b[j - 1]) {
+                ADJACENT_KEY_COST
+            } else {
+                1.0
+            };
+            let mut best = (d[i - 1][j] + 1.0).min(d[i][j - 1] + 1.0).min(d[i - 1][j - 1] + substitution);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(d[i - 2][j - 2] + TRANSPOSITION_COST);
+            }
+            d[i][j] = best;
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// Rows of a US QWERTY keyboard with each row's horizontal offset in keys.
+const KEYBOARD: [(&str, f64); 4] = [
+    ("1234567890-=", 0.0),
+    ("qwertyuiop[]", 0.5),
+    ("asdfghjkl;'", 0.75),
+    ("zxcvbnm,./", 1.25),
+];
+
+fn key_position(c: char) -> Option<(f64, f64)> {
+    let c = c.to_ascii_lowercase();
+    KEYBOARD.iter().enumerate().find_map(|(row, (keys, offset))| {
+        keys.chars().position(|k| k == c).map(|col| (row as f64, col as f64 + offset))
+    })
+}
+
+fn keys_adjacent(a: char, b: char) -> bool {
+    match (key_position(a), key_position(b)) {
+        (Some((ra, ca)), Some((rb, cb))) => (ra - rb).abs() <= 1.0 && (ca - cb).abs() <= 1.0,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executables::ExecutableIndex;
+
+    #[test]
+    fn test_typo_distance() {
+        assert_eq!(typo_distance("gti", "git"), TRANSPOSITION_COST);
+        assert_eq!(typo_distance("cargp", "cargo"), ADJACENT_KEY_COST);
+        assert_eq!(typo_distance("cargz", "cargo"), 1.0);
+        assert_eq!(typo_distance("carg", "cargo"), 1.0);
+        assert_eq!(typo_distance("", "ls"), 2.0);
+    }
+
+    #[test]
+    fn test_keys_adjacent() {
+        assert!(keys_adjacent('o', 'p'));
+        assert!(keys_adjacent('g', 'b'));
+        assert!(keys_adjacent('s', 'w'));
+        assert!(!keys_adjacent('q', 'p'));
+        assert!(!keys_adjacent('a', 'é'));
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let scorer = DefaultScorer::default();
+        assert!(scorer.score("git", "git").is_none());
+        assert!(scorer.score("xy", "ls").is_none());
+
+        let strict = DefaultScorer::new(0.5, 0.5);
+        assert!(strict.score("gti", "git").is_some());
+        assert!(strict.score("carg", "cargo").is_none());
+    }
+
+    #[test]
+    fn test_frequency_breaks_ties() {
+        let scorer = DefaultScorer::default()
+            .with_frequencies(command_frequencies(&["vim a".to_string(), "vim b".to_string()]));
+        // Both one unrelated edit away and sharing a one-letter prefix
+        assert!(scorer.score("vix", "vim").unwrap() > scorer.score("vix", "vi").unwrap());
+    }
+
+    const VOCABULARY: &str = "git grep egrep cat cp cd ls ll mv rm mkdir make cargo npm npx node python python3 \
+        pip pip3 docker kubectl ssh scp sudo apt apt-get brew vim vi nvim nano less more tail head find curl wget \
+        tar unzip chmod chown echo touch top htop ps kill man which sed awk go java javac rustc rustup yarn pnpm \
+        terraform helm systemctl journalctl gcc clang code tmux history diff sort uniq wc xargs clear tree";
+
+    fn corpus() -> impl Iterator<Item = (&'static str, &'static str)> {
+        include_str!("typo_corpus.txt").lines()
+            .filter(|l| !l.trim().is_empty() && !l.starts_with('#'))
+            .map(|l| l.split_once(' ').unwrap())
+    }
+
+    #[test]
+    fn test_search_radius_shrinks_for_short_words() {
+        let scorer = DefaultScorer::default();
+        assert_eq!(scorer.search_radius("sl"), 1);
+        assert_eq!(scorer.search_radius("gti"), 2);
+        assert_eq!(scorer.search_radius("systemclt"), 6);
+
+        let within = |typed: &str| -> Vec<&str> {
+            VOCABULARY.split_whitespace()
+                .filter(|c| strsim::damerau_levenshtein(typed, c) <= scorer.search_radius(typed))
+                .collect()
+        };
+        assert!(within("gti").len() <= 10, "{:?}", within("gti"));
+
+        // Every candidate `score` accepts is within the radius
+        for typed in corpus().map(|(typed, _)| typed).chain(["sl", "gti", "x"]) {
+            for candidate in VOCABULARY.split_whitespace().filter(|c| scorer.score(typed, c).is_some()) {
+                assert!(within(typed).contains(&candidate), "{} -> {}", typed, candidate);
+            }
+        }
+    }
+
+    /// Real typos, `typed expected` per line, must pick `expected` out of a
+    /// vocabulary of common commands, looked up the way `unknown_command` does.
+    #[test]
+    fn test_typo_corpus() {
+        let index = ExecutableIndex::from_names(VOCABULARY.split_whitespace());
+        let scorer = DefaultScorer::default();
+
+        let mut failures = Vec::new();
+        for (typed, expected) in corpus() {
+            let best = index.find_similar(typed, scorer.search_radius(typed)).into_iter()
+                .filter_map(|(c, _)| scorer.score(typed, &c).map(|s| (s, c)))
+                .max_by(|a, b| a.0.total_cmp(&b.0))
+                .map(|(_, c)| c);
+            if best.as_deref() != Some(expected) {
+                failures.push(format!("{} -> {:?}, expected {}", typed, best, expected));
+            }
+        }
+        assert!(failures.is_empty(), "{:#?}", failures);
+    }
+}
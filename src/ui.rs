@@ -1,6 +1,11 @@
-use dialoguer::{Select, theme::ColorfulTheme};
+use dialoguer::{Confirm, Select, theme::ColorfulTheme};
+use dialoguer::console::{Key, Term, truncate_str};
+use crate::engine::{CorrectionStream, RuleResult};
 use crate::types::Correction;
 use colored::*;
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
 
 pub fn select_correction(corrections: &[Correction]) -> Option<&Correction> {
     if corrections.is_empty() {
@@ -31,3 +36,193 @@ pub fn select_correction(corrections: &[Correction]) -> Option<&Correction> {
         None
     }
 }
+
+/// What the user did with a streamed list of corrections.
+#[derive(Debug)]
+pub enum Selection {
+    Chosen(Correction),
+    Cancelled,
+    NoCorrections,
+}
+
+enum Event {
+    Rule(RuleResult),
+    Finished,
+    Key(Key),
+}
+
+/// A correction in the list, with what it is ordered by.
+struct Entry {
+    correction: Correction,
+    rule_index: usize,
+    id: usize,
+}
+
+/// Shows the first correction as soon as a rule produces one and adds the
+/// rest as their rules finish, ordered by priority, then rule order. Once the
+/// user moves the selection the top entry stays put so Enter doesn't pick
+/// something they haven't seen.
+pub fn select_streamed_correction(stream: CorrectionStream) -> Selection {
+    let term = Term::stderr();
+    if !term.is_term() {
+        // Nothing to draw on, so wait for every rule
+        let corrections = stream.evaluate().corrections;
+        return match select_correction(&corrections) {
+            Some(correction) => Selection::Chosen(correction.clone()),
+            None if corrections.is_empty() => Selection::NoCorrections,
+            None => Selection::Cancelled,
+        };
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let rule_tx = tx.clone();
+    thread::spawn(move || {
+        for result in stream {
+            if rule_tx.send(Event::Rule(result)).is_err() {
+                return;
+            }
+        }
+        let _ = rule_tx.send(Event::Finished);
+    });
+
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut finished = false;
+
+    // Keys are only read once there is something to choose, and the reader
+    // stops after the key that ends the selection, so the terminal is never
+    // left in raw mode by a pending read.
+    while entries.is_empty() {
+        match rx.recv() {
+            Ok(Event::Rule(result)) => add(&mut entries, result, false),
+            _ => return Selection::NoCorrections,
+        }
+    }
+
+    let key_term = term.clone();
+    thread::spawn(move || loop {
+        let key = key_term.read_key().unwrap_or(Key::Escape);
+        let last = matches!(key, Key::Enter | Key::Escape | Key::CtrlC | Key::Char('q'));
+        if tx.send(Event::Key(key)).is_err() || last {
+            return;
+        }
+    });
+
+    let _ = term.hide_cursor();
+    let mut cursor = 0;
+    let mut moved = false;
+    let mut drawn = 0;
+    let selection = loop {
+        let _ = term.clear_last_lines(drawn);
+        drawn = draw(&term, &entries, cursor, finished);
+
+        let selected_id = entries.get(cursor).map(|e| e.id);
+        match rx.recv() {
+            Ok(Event::Rule(result)) => {
+                add(&mut entries, result, moved);
+                // Keep the cursor on the same correction, or on the best one
+                // until the user picks another
+                if let Some(id) = selected_id.filter(|_| moved) {
+                    cursor = entries.iter().position(|e| e.id == id).unwrap_or(cursor);
+                }
+            }
+            Ok(Event::Finished) => finished = true,
+            Ok(Event::Key(Key::ArrowUp | Key::Char('k'))) => {
+                cursor = cursor.checked_sub(1).unwrap_or(entries.len());
+                moved = true;
+            }
+            Ok(Event::Key(Key::ArrowDown | Key::Tab | Key::Char('j'))) => {
+                cursor = (cursor + 1) % (entries.len() + 1);
+                moved = true;
+            }
+            Ok(Event::Key(Key::Enter)) => match entries.get(cursor) {
+                Some(entry) => break Selection::Chosen(entry.correction.clone()),
+                None => break Selection::Cancelled,
+            },
+            Ok(Event::Key(Key::Escape | Key::CtrlC | Key::Char('q'))) | Err(_) => break Selection::Cancelled,
+            Ok(Event::Key(_)) => {}
+        }
+    };
+
+    let _ = term.clear_last_lines(drawn);
+    let _ = term.show_cursor();
+    if let Selection::Chosen(correction) = &selection {
+        let _ = term.write_line(&format!("{} {} {}", "✔".green(), "Select a correction:".bold(), correction.command));
+    }
+    selection
+}
+
+/// Adds the corrections of a finished rule and re-sorts the list, below the
+/// first entry if `keep_first`. A command already listed by another rule keeps
+/// the higher of the two priorities.
+fn add(entries: &mut Vec<Entry>, result: RuleResult, keep_first: bool) {
+    for correction in result.corrections.unwrap_or_default() {
+        match entries.iter_mut().find(|e| e.correction.command == correction.command) {
+            Some(entry) => entry.correction.priority = entry.correction.priority.max(correction.priority),
+            None => {
+                let id = entries.len();
+                entries.push(Entry { correction, rule_index: result.index, id });
+            }
+        }
+    }
+    let start = usize::from(keep_first).min(entries.len());
+    entries[start..].sort_by_key(|e| (std::cmp::Reverse(e.correction.priority), e.rule_index, e.id));
+}
+
+/// Draws the list with `cursor` highlighted, the last row being Cancel.
+/// Returns the number of lines drawn.
+fn draw(term: &Term, entries: &[Entry], cursor: usize, finished: bool) -> usize {
+    let width = usize::from(term.size().1).saturating_sub(1);
+    let searching = if finished { String::new() } else { format!(" {}", "(looking for more…)".dimmed()) };
+    let _ = term.write_line(&format!("{} {}{}", "?".yellow(), "Select a correction:".bold(), searching));
+
+    let rows = entries.iter()
+        .map(|e| format!("{}{}", e.correction.command, if e.correction.side_effect { " (side effect)" } else { "" }))
+        .chain(std::iter::once("Cancel".to_string()));
+    for (i, row) in rows.enumerate() {
+        let row = truncate_str(&row, width.saturating_sub(2), "…");
+        let line = if i == cursor { format!("{} {}", "❯".green(), row.bold()) } else { format!("  {}", row) };
+        let _ = term.write_line(&line);
+    }
+    entries.len() + 2
+}
+
+/// Asks before running rules checked into a project. Defaults to no, including
+/// when there is no terminal to ask on.
+pub fn confirm_trust(dir: &Path) -> bool {
+    Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!("Run project rules from {}?", dir.display()))
+        .default(false)
+        .interact()
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn result(index: usize, corrections: &[(&str, usize)]) -> RuleResult {
+        RuleResult {
+            index,
+            rule: format!("rule{}", index),
+            corrections: Some(corrections.iter().map(|(c, p)| Correction::new(c.to_string(), false, *p)).collect()),
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    fn commands(entries: &[Entry]) -> Vec<&str> {
+        entries.iter().map(|e| e.correction.command.as_str()).collect()
+    }
+
+    #[test]
+    fn test_add_resorts_until_moved() {
+        let mut entries = Vec::new();
+        add(&mut entries, result(1, &[("ls -l", 50)]), false);
+        add(&mut entries, result(0, &[("git status", 90), ("ls -l", 60)]), false);
+        assert_eq!(commands(&entries), vec!["git status", "ls -l"]);
+        assert_eq!(entries[1].correction.priority, 60);
+
+        add(&mut entries, result(2, &[("make", 100), ("cargo build", 70)]), true);
+        assert_eq!(commands(&entries), vec!["git status", "make", "cargo build", "ls -l"]);
+    }
+}
@@ -1,25 +1,138 @@
+use crate::shells::Shell;
 use std::env;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+/// How much of the end of the session log instant mode searches.
+const OUTPUT_LOG_TAIL: u64 = 1024 * 1024;
+
+/// The command to fix: the most recent entry in `TF_HISTORY` as exported by the
+/// shell function, or, when ffs is run directly or from a keybinding, in the
+/// shell's history file. Calls to ffs itself are skipped.
+pub fn get_last_command(shell: Option<&dyn Shell>) -> Option<String> {
+    let alias = env::var("TF_ALIAS").ok();
 
-pub fn get_last_command() -> Option<String> {
     if let Ok(history) = env::var("TF_HISTORY") {
-        let lines: Vec<&str> = history.trim().split('\n').collect();
-        // The history is usually in order?
-        // thefuck reverses it. "fc -ln -10" output order depends on shell but usually oldest first?
-        // Wait, "fc -ln -10" in bash outputs the *last 10 commands*.
-        // Typically they are in chronological order.
-        // So the last one is the most recent.
-        // But the *very* last one might be the `ffs` (alias) call itself if the shell adds it to history immediately?
-        // Usually alias execution is added to history *after*.
-
-        // Let's assume the last line is the most recent.
-        // We iterate backwards.
-
-        for line in lines.iter().rev() {
-            let trimmed = line.trim();
-            if !trimmed.is_empty() && !trimmed.starts_with("ffs") && !trimmed.starts_with("fuck") {
-                return Some(trimmed.to_string());
+        let entries = match shell {
+            Some(shell) => shell.parse_tf_history(&history),
+            None => history.lines().map(str::to_string).collect(),
+        };
+        return last_command(entries, alias.as_deref());
+    }
+
+    // Bash and zsh only write the file on exit unless configured to append
+    // after every command, so this can lag behind the session.
+    shell.and_then(|s| last_command(s.get_history(), alias.as_deref()))
+}
+
+/// The newest entry that isn't a call to ffs, given entries oldest first.
+pub fn last_command(entries: Vec<String>, alias: Option<&str>) -> Option<String> {
+    entries.into_iter()
+        .rev()
+        .map(|entry| entry.trim().to_string())
+        .find(|entry| !entry.is_empty() && !is_ffs_call(entry, alias))
+}
+
+/// In instant mode, the output `script` left in the session log at
+/// `TF_OUTPUT_LOG`, so it doesn't need to be re-run.
+pub fn get_logged_output(script: &str) -> Option<String> {
+    let mut file = File::open(env::var_os("TF_OUTPUT_LOG")?).ok()?;
+    let len = file.metadata().ok()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(OUTPUT_LOG_TAIL))).ok()?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).ok()?;
+    output_from_log(&String::from_utf8_lossy(&bytes), script)
+}
+
+/// The lines of a terminal log between the last line ending with `script`,
+/// where it was typed, and the last line, the prompt ffs was called from.
+/// `None` if the command line can't be found, e.g. because it was edited.
+pub fn output_from_log(log: &str, script: &str) -> Option<String> {
+    let log = log.replace("\r\n", "\n");
+    let start = log.rmatch_indices(script)
+        .map(|(i, _)| i + script.len())
+        .find(|&end| log[end..].trim_start_matches([' ', '\t']).starts_with('\n'))?;
+
+    let rest = &log[start..];
+    let rest = rest[rest.find('\n')? + 1..].trim_end_matches('\n');
+    Some(rest.rfind('\n').map_or("", |i| &rest[..i]).to_string())
+}
+
+pub(crate) fn is_ffs_call(entry: &str, alias: Option<&str>) -> bool {
+    let program = entry.split_whitespace().next().unwrap_or_default();
+    program == "ffs" || program == "fuck" || Some(program) == alias
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shells::{Bash, Zsh};
+    use serial_test::serial;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_last_command_skips_alias() {
+        let entries = vec!["git psuh".to_string(), "ffsck /dev/sda".to_string(), "oops".to_string(), "   ".to_string()];
+        assert_eq!(last_command(entries.clone(), None), Some("oops".to_string()));
+        assert_eq!(last_command(entries, Some("oops")), Some("ffsck /dev/sda".to_string()));
+    }
+
+    #[test]
+    fn test_output_from_log() {
+        let log = "$ ls\r\nsrc\r\n$ git brnch\r\ngit: 'brnch' is not a git command.\r\n\r\nThe most similar command is\r\n\tbranch\r\n$ fuck\r\n";
+        assert_eq!(
+            output_from_log(log, "git brnch"),
+            Some("git: 'brnch' is not a git command.\n\nThe most similar command is\n\tbranch".to_string())
+        );
+        assert_eq!(output_from_log("$ true\n$ fuck\n", "true"), Some("".to_string()));
+        // Typed text must end the line, so output mentioning the command doesn't count
+        assert_eq!(output_from_log("$ cat x\r\ncat x: no\r\n$ fuck\r\n", "cat x"), Some("cat x: no".to_string()));
+        assert_eq!(output_from_log("$ git brn\x08nch\r\n$ fuck\r\n", "git brnch"), None);
+    }
+
+    #[test]
+    #[serial]
+    fn test_get_last_command_multiline() {
+        let original_tf_history = env::var("TF_HISTORY").ok();
+        let original_tf_alias = env::var("TF_ALIAS").ok();
+        env::set_var("TF_HISTORY", "\t git status\n\t cat <<END\nhello\nEND\n\n\t damn");
+        env::set_var("TF_ALIAS", "damn");
+
+        let command = get_last_command(Some(&Bash));
+
+        for (key, value) in [("TF_HISTORY", original_tf_history), ("TF_ALIAS", original_tf_alias)] {
+            match value {
+                Some(v) => env::set_var(key, v),
+                None => env::remove_var(key),
             }
         }
+
+        assert_eq!(command, Some("cat <<END\nhello\nEND".to_string()));
+    }
+
+    #[test]
+    #[serial]
+    fn test_get_last_command_from_history_file() {
+        let dir = tempdir().unwrap();
+        let histfile = dir.path().join(".zsh_history");
+        fs::write(&histfile, ": 1700000000:0;ls\n: 1700000001:0;git psuh\n: 1700000002:0;ffs\n").unwrap();
+
+        let original_tf_history = env::var("TF_HISTORY").ok();
+        let original_histfile = env::var("HISTFILE").ok();
+        env::remove_var("TF_HISTORY");
+        env::set_var("HISTFILE", &histfile);
+
+        let command = get_last_command(Some(&Zsh));
+
+        match original_histfile {
+            Some(h) => env::set_var("HISTFILE", h),
+            None => env::remove_var("HISTFILE"),
+        }
+        if let Some(h) = original_tf_history {
+            env::set_var("TF_HISTORY", h);
+        }
+
+        assert_eq!(command, Some("git psuh".to_string()));
     }
-    None
 }